@@ -0,0 +1,78 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.9;
+
+/// @notice On-chain mirror of `KeccakHasher` in order-engine/lib. Only valid when the
+/// order program is built with the `keccak` feature.
+library OrderHashLib {
+    /// @notice Must match `TREE_DEPTH` in order-engine/lib
+    uint256 internal constant TREE_DEPTH = 20;
+
+    /// @notice Mirrors `OrderData`. `side` is 0 for a sell order and 1 for a buy order, as
+    /// `OrderSide`; prices are quote units per `10 ** baseDecimals` base units. `kind` is 0 for
+    /// a limit, 1 for a stop-loss and 2 for a take-profit order, as `OrderKind`, with a zero
+    /// `triggerPrice` for a limit order; `timeInForce` is 0 for GTC, 1 for IOC and 2 for FOK
+    struct Order {
+        address walletAddress;
+        address tokenIn;
+        address tokenOut;
+        uint256 amountIn;
+        uint256 minAmountOut;
+        uint256 targetPrice;
+        uint256 deadline;
+        uint8 side;
+        uint8 baseDecimals;
+        uint8 kind;
+        uint256 triggerPrice;
+        uint8 timeInForce;
+        uint256 validAfter;
+    }
+
+    /// @notice Every field is static, so this is the same encoding as `abi.encode` over the
+    /// fields in order
+    function hashOrder(Order memory order) internal pure returns (bytes32) {
+        return keccak256(abi.encode(order));
+    }
+
+    function nullifierHash(bytes32 nullifier) internal pure returns (bytes32) {
+        return keccak256(abi.encode(bytes32("NULLIFIER_HASH"), nullifier));
+    }
+
+    function commitmentHash(bytes32 orderHash, bytes32 nullifier, address balanceAsset, uint256 balance)
+        internal
+        pure
+        returns (bytes32)
+    {
+        return keccak256(abi.encode(bytes32("COMMITMENT_HASH"), orderHash, nullifier, balanceAsset, balance));
+    }
+
+    function nullifierLeaf(bytes32 value, bytes32 nextValue, uint64 nextIndex)
+        internal
+        pure
+        returns (bytes32)
+    {
+        return keccak256(abi.encode(bytes32("NULLIFIER_LEAF"), value, nextValue, uint256(nextIndex)));
+    }
+
+    function merkleLeaf(bytes32 commitmentHash) internal pure returns (bytes32) {
+        return keccak256(abi.encode(bytes32("MERKLE_LEAF"), commitmentHash));
+    }
+
+    function merkleNode(bytes32 left, bytes32 right) internal pure returns (bytes32) {
+        return keccak256(abi.encode(bytes32("MERKLE_NODE"), left, right));
+    }
+
+    /// @notice Recomputes a tree root from a commitment and its path (bit i of leafIndex = 0 when
+    /// the node at level i is the left child)
+    function merkleRoot(bytes32 commitmentHash, bytes32[] memory siblings, uint64 leafIndex)
+        internal
+        pure
+        returns (bytes32 node)
+    {
+        require(siblings.length == TREE_DEPTH, "Merkle path length mismatch");
+        require(leafIndex >> TREE_DEPTH == 0, "Leaf index out of range");
+        node = merkleLeaf(commitmentHash);
+        for (uint256 i = 0; i < siblings.length; i++) {
+            node = (leafIndex >> i) & 1 == 0 ? merkleNode(node, siblings[i]) : merkleNode(siblings[i], node);
+        }
+    }
+}
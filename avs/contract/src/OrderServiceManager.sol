@@ -17,6 +17,7 @@ import {TransparentUpgradeableProxy} from
     "@openzeppelin/contracts/proxy/transparent/TransparentUpgradeableProxy.sol";
 
 import {ISP1Verifier} from "sp1-contracts/src/ISP1Verifier.sol";
+import {OrderHashLib} from "./OrderHashLib.sol";
 
 
 
@@ -38,12 +39,22 @@ interface IDarkCoWHook {
         TransferBalance[] memory transferBalances,
         SwapBalance[] memory swapBalances
     ) external;
+
+    // The `PoolKey` the hook recorded for a pool; zero for a pool it never saw
+    function poolKeys(bytes32 poolId)
+        external
+        view
+        returns (address currency0, address currency1, uint24 fee, int24 tickSpacing, address hooks);
 }
 
 contract OrderServiceManager is ECDSAServiceManagerBase, IOrderServiceManager {
     using ECDSAUpgradeable for bytes32;
 
     event TaskResponded(uint32 indexed taskIndex, Task task, address operator);
+    event NullifiersSpent(bytes32[] nullifierHashes, bytes32 spentNullifierRoot);
+    event MarketPriceReported(bytes32 indexed pair, uint64 timestamp, uint256 price);
+    // A new note for the indexer to append to the commitment tree
+    event NoteCommitted(bytes32 commitmentHash);
     event ProveRequest(
         uint32 indexed taskIndex,
         address indexed operator,
@@ -56,6 +67,24 @@ contract OrderServiceManager is ECDSAServiceManagerBase, IOrderServiceManager {
     mapping(address => mapping(uint32 => bytes)) public allTaskResponses;
     address public verifier;
     bytes32 public orderProgramVKey;
+    // Verification key of the batch order program (`batch` in order-engine/program)
+    bytes32 public batchProgramVKey;
+    // Verification key of the settlement program (`settlement` in order-engine/program)
+    bytes32 public settlementProgramVKey;
+    // Verification key of the aggregation program (`aggregation` in order-engine/program)
+    bytes32 public aggregationProgramVKey;
+    // The order program's vkey as the aggregation program commits it (`vk.hash_u32()` as big-endian words)
+    bytes32 public orderProgramVKeyDigest;
+    // Root of the spent-nullifier tree maintained off-chain; proofs show their nullifier is absent from it.
+    // Only the current root is accepted: an older one lacks the nullifiers spent since
+    bytes32 public spentNullifierRoot;
+    // Commitment-tree roots whose deposits are on chain; proofs may open any of them
+    mapping(bytes32 => bool) public knownTreeRoots;
+    // Price of a pair's base token in its quote token, by pair (see `marketPair`) and the
+    // timestamp it was reported at; proofs must use one of these as their market snapshot
+    mapping(bytes32 => mapping(uint64 => uint256)) public marketPrices;
+    // How long a reported price stays usable as a proof's market snapshot
+    uint64 public constant MAX_MARKET_AGE = 5 minutes;
 
     // uint32 public immutable MAX_RESPONSE_INTERVAL_BLOCKS;
 
@@ -72,10 +101,41 @@ contract OrderServiceManager is ECDSAServiceManagerBase, IOrderServiceManager {
         _;
     }
     
-    struct PublicValuesStruct {
-        uint32 n;
-        uint32 a;
-        uint32 b;
+    // Mirrors `OrderPublicValues` in order-engine/lib, committed by the order program
+    struct OrderPublicValues {
+        bool valid;
+        bytes32 nullifierHash;
+        address walletAddress;
+        uint256 amountIn;
+        uint256 minAmountOut;
+        bytes32 treeRoot;
+        bytes32 nullifierRoot;
+        bytes32 changeCommitment;
+        address tokenIn;
+        address tokenOut;
+        uint64 deadline;
+        uint256 currentPrice;
+        uint64 blockTimestamp;
+    }
+
+    // Mirrors `BatchPublicValues` in order-engine/lib, committed by the batch order program
+    struct BatchPublicValues {
+        OrderPublicValues[] orders;
+    }
+
+    // Mirrors `AggregatePublicValues` in order-engine/lib, committed by the aggregation program
+    struct AggregatePublicValues {
+        bytes32 orderVKeyDigest;
+        OrderPublicValues[] orders;
+    }
+
+    // Mirrors `SettlementPublicValues` in order-engine/lib, committed by the settlement program
+    struct SettlementPublicValues {
+        bytes32 settlementHash;
+        address currency0;
+        address currency1;
+        bytes32 newNullifierRoot;
+        OrderPublicValues[] orders;
     }
 
     struct ProveRequestData {
@@ -90,10 +150,23 @@ contract OrderServiceManager is ECDSAServiceManagerBase, IOrderServiceManager {
         uint256 minAmountOut;
         uint256 targetPrice;
         uint256 deadline;
+        // Coded as in `OrderHashLib.Order`
+        uint8 side;
+        uint8 baseDecimals;
+        uint8 kind;
+        uint256 triggerPrice;
+        uint8 timeInForce;
+        uint256 validAfter;
         bytes32 commitmentNullifier;
+        address balanceAsset;
         uint256 balance;
         bytes32[] siblings;
-        uint32[] indices;
+        uint64 leafIndex;
+        // Part of the order filled now
+        uint256 fillAmount;
+        // Order of the change note, when the prover should not derive it from what is left
+        bool hasChangeOrder;
+        OrderHashLib.Order changeOrder;
     }
 
     ProveRequestData proveData;
@@ -117,12 +190,14 @@ contract OrderServiceManager is ECDSAServiceManagerBase, IOrderServiceManager {
     {
         verifier = _verifier;
         orderProgramVKey = _orderProgramVKey;
-        // Initialize hardcoded prove data from RequestBody.json
+        // Initialize hardcoded prove data from RequestBody.json: the third note in a
+        // SHA-256 commitment tree, with its TREE_DEPTH (20) siblings. The order is filled
+        // in full, and the change note offers the other 5 ETH on the same terms
         proveData = ProveRequestData({
             marketCurrentPrice: bytes32(uint256(2050000000)),
             marketBlockTimestamp: 1735600000,
-            treeRoot: 0x1111111111111111111111111111111111111111111111111111111111111111,
-            nullifierHash: 0x2222222222222222222222222222222222222222222222222222222222222222,
+            treeRoot: 0xfd792033b95723c05a4eec37f975fd4f626c4675c38d4a59625903eef5db1707,
+            nullifierHash: 0x5289617118f8592ba0ae0fa65e981a3d24ad43104040c8083a7991ab2ac9402e,
             walletAddress: 0x0000000000000000000000000000000000000001,
             tokenIn: 0xaAaAaAaaAaAaAaaAaAAAAAAAAaaaAaAaAaaAaaAa,
             tokenOut: 0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB,
@@ -130,15 +205,55 @@ contract OrderServiceManager is ECDSAServiceManagerBase, IOrderServiceManager {
             minAmountOut: 10000000000,
             targetPrice: 2000000000,
             deadline: 1735689600,
-            commitmentNullifier: 0x3333333333333333333333333333333333333333333333333333333333333333,
+            side: 0,
+            baseDecimals: 18,
+            kind: 0,
+            triggerPrice: 0,
+            timeInForce: 0,
+            validAfter: 0,
+            commitmentNullifier: 0x2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a,
+            balanceAsset: 0xaAaAaAaaAaAaAaaAaAAAAAAAAaaaAaAaAaaAaaAa,
             balance: 10000000000000000000,
-            siblings: new bytes32[](2),
-            indices: new uint32[](2)
+            siblings: new bytes32[](20),
+            leafIndex: 2,
+            fillAmount: 5000000000000000000,
+            hasChangeOrder: true,
+            changeOrder: OrderHashLib.Order({
+                walletAddress: 0x0000000000000000000000000000000000000001,
+                tokenIn: 0xaAaAaAaaAaAaAaaAaAAAAAAAAaaaAaAaAaaAaaAa,
+                tokenOut: 0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB,
+                amountIn: 5000000000000000000,
+                minAmountOut: 10000000000,
+                targetPrice: 2000000000,
+                deadline: 1735689600,
+                side: 0,
+                baseDecimals: 18,
+                kind: 0,
+                triggerPrice: 0,
+                timeInForce: 0,
+                validAfter: 0
+            })
         });
-        proveData.siblings[0] = 0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa;
-        proveData.siblings[1] = 0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb;
-        proveData.indices[0] = 0;
-        proveData.indices[1] = 1;
+        proveData.siblings[0] = 0x0000000000000000000000000000000000000000000000000000000000000000;
+        proveData.siblings[1] = 0xf2bd6ad3f673d18986562d5c984f1c0d3bc23bf6825b58222901e4b0f20996e7;
+        proveData.siblings[2] = 0x83e774b36b80c07d6df472f97f84b3a3650f2aa3519bffecf781d55db96028ae;
+        proveData.siblings[3] = 0xef09f98b7649f9604f227c2188e0534b901a42cee0e6f39cb8b54ee0f168cb43;
+        proveData.siblings[4] = 0xdbbac33b1c199b6602c7107937c82e3977e68bb252d3e5c33bd5b6b218d05dd0;
+        proveData.siblings[5] = 0x676bd971665f66eac92731e5eb4d88894107e8552e2d16aaffbd0aa07c0641b8;
+        proveData.siblings[6] = 0xc3ad5b19e75999104e87ead9d554efca0b3ae97b2a4e07c692f4465eb5d7a49c;
+        proveData.siblings[7] = 0xa06bb0655e4a9fdd7fddb78b0396a28c0241bea9b3adedf9b50bf77e53570ed9;
+        proveData.siblings[8] = 0xcaee56935f1249dde3e84a6f7d53647e101d33a12e19a2cd01228358c2a791bd;
+        proveData.siblings[9] = 0x8b8c6d4ba538dc19cce521772f320bc5221c16d381e525edba509ed95292364f;
+        proveData.siblings[10] = 0x067a334503edf795e0f3f2b5f83c16d1226dcbdf949b361107dd896525e3d1ca;
+        proveData.siblings[11] = 0xd7b12a51224bdf04855d94e97fbe77b43b6538ca4f6f8021d6fa2e8c968d1d20;
+        proveData.siblings[12] = 0x84d827ace3a3eaa61cbddddc9f99c9d7af03ea74a0cdf008ac09a1e804245d48;
+        proveData.siblings[13] = 0xca98eb3b8b0f8b3a24c827145d1280af21e609f51ec93faff20ad40d51fa3d56;
+        proveData.siblings[14] = 0x2f3225b88b85a6f9123af04edaec97c624d82af374843d4a21534c21ba612ec1;
+        proveData.siblings[15] = 0xea032161030bf0783db2522f47c1b54c2cbacc71e999cab498e040853b087d2d;
+        proveData.siblings[16] = 0x153fee53b5b045d0ddabc059692a01162c4669caa736d45b2119f87a75f3359b;
+        proveData.siblings[17] = 0x088b9f47f2788eafea09045d1de8803c472b071b71f87d823c9c9666987434a5;
+        proveData.siblings[18] = 0xd359a6502a567a6a463dcfb807f387caf3bd6a208e0ab5cb29fa0049b658aa06;
+        proveData.siblings[19] = 0x44d5b26463367d100d374c4ab152420519b404b250ccd79581978d2ff5075fcb;
     }
 
     function initialize(address initialOwner, address _rewardsInitiator) external initializer {
@@ -200,11 +315,14 @@ contract OrderServiceManager is ECDSAServiceManagerBase, IOrderServiceManager {
 
     //operators to respond to tasks, batch of orders (order = a single a task)
     //Firstly, Verification using the sp1-contracts
+    // `changeCommitment` is the change note the order proof commits, zero if the fill spends
+    // the whole note
     function respondToBatch(
         Task[] calldata tasks,
         uint32[] memory referenceTaskIndices,
         IDarkCoWHook.TransferBalance[] memory transferBalances,
         IDarkCoWHook.SwapBalance[] memory swapBalances,
+        bytes32 changeCommitment,
         bytes memory signature
     ) external {
         // check that the task is valid, hasn't been responded yet, and is being responded in time
@@ -235,32 +353,29 @@ contract OrderServiceManager is ECDSAServiceManagerBase, IOrderServiceManager {
         bytes32 poolIdToUse = tasks[0].poolId;
 
         // Create public values for proof verification
-        bytes memory publicValues = abi.encode(PublicValuesStruct({
-            n: uint32(proveData.indices.length),
-            a: uint32(proveData.amountIn),
-            b: uint32(proveData.minAmountOut)
+        bytes memory publicValues = abi.encode(OrderPublicValues({
+            valid: true,
+            nullifierHash: proveData.nullifierHash,
+            walletAddress: proveData.walletAddress,
+            amountIn: proveData.fillAmount,
+            minAmountOut: proveData.minAmountOut,
+            treeRoot: proveData.treeRoot,
+            nullifierRoot: spentNullifierRoot,
+            changeCommitment: changeCommitment,
+            tokenIn: proveData.tokenIn,
+            tokenOut: proveData.tokenOut,
+            deadline: uint64(proveData.deadline),
+            currentPrice: uint256(proveData.marketCurrentPrice),
+            blockTimestamp: uint64(proveData.marketBlockTimestamp)
         }));
 
         // Verify the order proof - convert memory to calldata-compatible format
         verifyOrderProofInternal(publicValues, signature);
+        if (changeCommitment != bytes32(0)) {
+            emit NoteCommitted(changeCommitment);
+        }
 
-        ProveRequestData memory args = ProveRequestData(
-            proveData.marketCurrentPrice,
-            proveData.marketBlockTimestamp,
-            proveData.treeRoot,
-            proveData.nullifierHash,
-            proveData.walletAddress,
-            proveData.tokenIn,
-            proveData.tokenOut,
-            proveData.amountIn,
-            proveData.minAmountOut,
-            proveData.targetPrice,
-            proveData.deadline,
-            proveData.commitmentNullifier,
-            proveData.balance,
-            proveData.siblings,
-            proveData.indices
-        );
+        ProveRequestData memory args = proveData;
         
         // Emit prove request event for off-chain processing
         emit ProveRequest(
@@ -284,22 +399,157 @@ contract OrderServiceManager is ECDSAServiceManagerBase, IOrderServiceManager {
     function verifyOrderProofInternal(bytes memory _publicValues, bytes memory _proofBytes)
         internal
         view
-        returns (uint32, uint32, uint32)
+        returns (OrderPublicValues memory)
     {
         ISP1Verifier(verifier).verifyProof(orderProgramVKey, _publicValues, _proofBytes);
-        PublicValuesStruct memory publicValues = abi.decode(_publicValues, (PublicValuesStruct));
-        return (publicValues.n, publicValues.a, publicValues.b);
+        return checkOrder(abi.decode(_publicValues, (OrderPublicValues)));
     }
 
     // Public function for external calls with proper calldata parameters
     function verifyOrderProof(bytes calldata _publicValues, bytes calldata _proofBytes)
         public
         view
-        returns (uint32, uint32, uint32)
+        returns (OrderPublicValues memory)
     {
         ISP1Verifier(verifier).verifyProof(orderProgramVKey, _publicValues, _proofBytes);
-        PublicValuesStruct memory publicValues = abi.decode(_publicValues, (PublicValuesStruct));
-        return (publicValues.n, publicValues.a, publicValues.b);
+        return checkOrder(abi.decode(_publicValues, (OrderPublicValues)));
+    }
+
+    // Applies the batch checks to a single order proof
+    function checkOrder(OrderPublicValues memory order)
+        internal
+        view
+        returns (OrderPublicValues memory)
+    {
+        OrderPublicValues[] memory orders = new OrderPublicValues[](1);
+        orders[0] = order;
+        checkRoots(orders);
+        checkMarket(orders);
+        return order;
+    }
+
+    // Verifies one proof for a whole batch of orders and returns each order's public values
+    function verifyBatchProof(bytes calldata _publicValues, bytes calldata _proofBytes)
+        public
+        view
+        returns (OrderPublicValues[] memory)
+    {
+        ISP1Verifier(verifier).verifyProof(batchProgramVKey, _publicValues, _proofBytes);
+        OrderPublicValues[] memory orders = abi.decode(_publicValues, (BatchPublicValues)).orders;
+        checkRoots(orders);
+        checkMarket(orders);
+        return orders;
+    }
+
+    // Verifies one proof aggregating many order program proofs and returns each order's public values
+    function verifyAggregatedProof(bytes calldata _publicValues, bytes calldata _proofBytes)
+        public
+        view
+        returns (OrderPublicValues[] memory)
+    {
+        ISP1Verifier(verifier).verifyProof(aggregationProgramVKey, _publicValues, _proofBytes);
+        AggregatePublicValues memory aggregate = abi.decode(_publicValues, (AggregatePublicValues));
+        require(
+            aggregate.orderVKeyDigest == orderProgramVKeyDigest,
+            "Aggregated proofs are not from the order program"
+        );
+        checkRoots(aggregate.orders);
+        checkMarket(aggregate.orders);
+        return aggregate.orders;
+    }
+
+    // Verifies a settlement proof and that it was made for exactly these transfer and swap arrays
+    function verifySettlementProof(
+        bytes calldata _publicValues,
+        bytes calldata _proofBytes,
+        IDarkCoWHook.TransferBalance[] memory transferBalances,
+        IDarkCoWHook.SwapBalance[] memory swapBalances
+    ) public view returns (SettlementPublicValues memory settlement) {
+        ISP1Verifier(verifier).verifyProof(settlementProgramVKey, _publicValues, _proofBytes);
+        settlement = abi.decode(_publicValues, (SettlementPublicValues));
+        require(
+            settlement.settlementHash == getSettlementHash(transferBalances, swapBalances),
+            "Settlement does not match the proof"
+        );
+        checkRoots(settlement.orders);
+        checkMarket(settlement.orders);
+    }
+
+    // Settles a proven batch in the hook's pool `poolId`: moves to the spent-nullifier root
+    // the proof reached by inserting the batch's nullifiers and pays out through the hook
+    function settleWithProof(
+        bytes32 poolId,
+        bytes calldata _publicValues,
+        bytes calldata _proofBytes,
+        IDarkCoWHook.TransferBalance[] memory transferBalances,
+        IDarkCoWHook.SwapBalance[] memory swapBalances
+    ) external onlyOperator {
+        SettlementPublicValues memory settlement =
+            verifySettlementProof(_publicValues, _proofBytes, transferBalances, swapBalances);
+
+        // The swaps run in the pool the proof priced them for
+        (address currency0, address currency1,,,) = IDarkCoWHook(hook).poolKeys(poolId);
+        require(currency1 != address(0), "Unknown pool");
+        require(
+            currency0 == settlement.currency0 && currency1 == settlement.currency1,
+            "Settlement is not for this pool"
+        );
+
+        bytes32[] memory nullifierHashes = new bytes32[](settlement.orders.length);
+        for (uint256 i = 0; i < settlement.orders.length; i++) {
+            nullifierHashes[i] = settlement.orders[i].nullifierHash;
+        }
+        spentNullifierRoot = settlement.newNullifierRoot;
+        emit NullifiersSpent(nullifierHashes, settlement.newNullifierRoot);
+
+        IDarkCoWHook(hook).settleBalances(poolId, transferBalances, swapBalances);
+    }
+
+    // Rejects orders proven against a stale spent-nullifier root or an unknown commitment
+    // root. A spent nullifier is in the current root, so no proof against it can omit it
+    function checkRoots(OrderPublicValues[] memory orders) internal view {
+        for (uint256 i = 0; i < orders.length; i++) {
+            require(
+                orders[i].nullifierRoot == spentNullifierRoot,
+                "Proof is not against the current spent-nullifier root"
+            );
+            require(knownTreeRoots[orders[i].treeRoot], "Proof is against an unknown tree root");
+        }
+    }
+
+    // Rejects orders proven at a price or time that was not reported here, or too long ago,
+    // and orders past their deadline, so no one can pick the market that triggers or
+    // revives an order
+    function checkMarket(OrderPublicValues[] memory orders) internal view {
+        for (uint256 i = 0; i < orders.length; i++) {
+            OrderPublicValues memory order = orders[i];
+            uint256 reported =
+                marketPrices[marketPair(order.tokenIn, order.tokenOut)][order.blockTimestamp];
+            require(
+                reported != 0 && reported == order.currentPrice,
+                "Proof's market price was not reported at its timestamp"
+            );
+            require(
+                block.timestamp - order.blockTimestamp <= MAX_MARKET_AGE,
+                "Proof's market price is stale"
+            );
+            require(block.timestamp <= order.deadline, "Order has expired");
+        }
+    }
+
+    // Key of a token pair in `marketPrices`, whichever order the tokens come in
+    function marketPair(address tokenA, address tokenB) public pure returns (bytes32) {
+        return tokenA < tokenB
+            ? keccak256(abi.encode(tokenA, tokenB))
+            : keccak256(abi.encode(tokenB, tokenA));
+    }
+
+    // The hash the settlement program commits for the arrays passed to `settleBalances`
+    function getSettlementHash(
+        IDarkCoWHook.TransferBalance[] memory transferBalances,
+        IDarkCoWHook.SwapBalance[] memory swapBalances
+    ) public pure returns (bytes32) {
+        return keccak256(abi.encode(transferBalances, swapBalances));
     }
 
     function getMessageHash(
@@ -341,6 +591,39 @@ contract OrderServiceManager is ECDSAServiceManagerBase, IOrderServiceManager {
         // // TODO: slash operator
     }
 
+    function setBatchProgramVKey(bytes32 _batchProgramVKey) external onlyOwner {
+        batchProgramVKey = _batchProgramVKey;
+    }
+
+    function setSettlementProgramVKey(bytes32 _settlementProgramVKey) external onlyOwner {
+        settlementProgramVKey = _settlementProgramVKey;
+    }
+
+    function setAggregationProgramVKey(
+        bytes32 _aggregationProgramVKey,
+        bytes32 _orderProgramVKeyDigest
+    ) external onlyOwner {
+        aggregationProgramVKey = _aggregationProgramVKey;
+        orderProgramVKeyDigest = _orderProgramVKeyDigest;
+    }
+
+    function setSpentNullifierRoot(bytes32 _spentNullifierRoot) external onlyOwner {
+        spentNullifierRoot = _spentNullifierRoot;
+    }
+
+    // Records the current price of a pair's base token in its quote token
+    function reportMarketPrice(address tokenA, address tokenB, uint256 price) external onlyOwner {
+        require(price != 0, "Price must be nonzero");
+        bytes32 pair = marketPair(tokenA, tokenB);
+        marketPrices[pair][uint64(block.timestamp)] = price;
+        emit MarketPriceReported(pair, uint64(block.timestamp), price);
+    }
+
+    // Records the commitment-tree root after deposits land on chain
+    function addTreeRoot(bytes32 _treeRoot) external onlyOwner {
+        knownTreeRoots[_treeRoot] = true;
+    }
+
     function setHook(address _hook) external {
         hook = _hook;
     }
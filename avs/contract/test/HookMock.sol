@@ -0,0 +1,17 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.12;
+
+import {IDarkCoWHook} from "../src/OrderServiceManager.sol";
+
+// Stands in for DarkCoWHook: counts the settlements it is asked to make
+contract HookMock {
+    uint256 public settlements;
+
+    function settleBalances(
+        bytes32,
+        IDarkCoWHook.TransferBalance[] memory,
+        IDarkCoWHook.SwapBalance[] memory
+    ) external {
+        settlements++;
+    }
+}
@@ -1,7 +1,7 @@
 // SPDX-License-Identifier: UNLICENSED
 pragma solidity ^0.8.12;
 
-import {OrderServiceManager} from "../src/OrderServiceManager.sol";
+import {OrderServiceManager, IDarkCoWHook} from "../src/OrderServiceManager.sol";
 import {MockAVSDeployer} from "@eigenlayer-middleware/test/utils/MockAVSDeployer.sol";
 import {ECDSAStakeRegistry} from "@eigenlayer-middleware/src/unaudited/ECDSAStakeRegistry.sol";
 import {Vm} from "forge-std/Vm.sol";
@@ -12,6 +12,8 @@ import {
 } from "../script/utils/CoreDeploymentParsingLib.sol";
 import {UpgradeableProxyLib} from "../script/utils/UpgradeableProxyLib.sol";
 import {ERC20Mock} from "./ERC20Mock.sol";
+import {HookMock} from "./HookMock.sol";
+import {ISP1Verifier} from "sp1-contracts/src/ISP1Verifier.sol";
 import {IERC20, StrategyFactory} from "@eigenlayer/contracts/strategies/StrategyFactory.sol";
 
 import {
@@ -557,6 +559,122 @@ contract RespondToTask is OrderTaskManagerSetup {
     }
 }
 
+contract RespondToBatch is OrderTaskManagerSetup {
+    // The sample order `respondToBatch` checks proofs against (config/RequestBody.json)
+    // fills 5 ETH of a 10 ETH note, so its proof commits this change note
+    bytes32 internal constant CHANGE_COMMITMENT =
+        0x0729d18e9dd707ff9d2c96c55198fdfc2dd17b1691d10238969bd96be01c9e49;
+    bytes32 internal constant TREE_ROOT =
+        0xfd792033b95723c05a4eec37f975fd4f626c4675c38d4a59625903eef5db1707;
+    address internal constant TOKEN_IN = 0xaAaAaAaaAaAaAaaAaAAAAAAAAaaaAaAaAaaAaaAa;
+    address internal constant TOKEN_OUT = 0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB;
+    uint64 internal constant MARKET_TIMESTAMP = 1735600000;
+
+    OrderServiceManager internal sm;
+    HookMock internal hook;
+    Vm.Wallet internal responder;
+
+    struct Response {
+        IOrderServiceManager.Task[] tasks;
+        uint32[] referenceTaskIndices;
+        IDarkCoWHook.TransferBalance[] transferBalances;
+        IDarkCoWHook.SwapBalance[] swapBalances;
+        bytes signature;
+    }
+
+    function setUp() public override {
+        super.setUp();
+        sm = OrderServiceManager(AVSDeployment.orderServiceManager);
+        hook = new HookMock();
+        sm.setHook(address(hook));
+        responder = vm.createWallet("batch_responder");
+
+        // The sample's market snapshot is reported when it is taken, and its tree root
+        // is on chain
+        vm.warp(MARKET_TIMESTAMP);
+        vm.startPrank(owner.key.addr);
+        sm.addTreeRoot(TREE_ROOT);
+        sm.reportMarketPrice(TOKEN_IN, TOKEN_OUT, 2050000000);
+        vm.stopPrank();
+    }
+
+    // A signed response to a new task, whose signature the verifier accepts as a proof
+    // of the sample order with its change note
+    function makeResponse() internal returns (Response memory response) {
+        vm.prank(address(hook));
+        IOrderServiceManager.Task memory task =
+            sm.createNewTask(true, -1 ether, 0, address(this), keccak256("Pool1"));
+
+        response.tasks = new IOrderServiceManager.Task[](1);
+        response.tasks[0] = task;
+        response.referenceTaskIndices = new uint32[](1);
+        response.referenceTaskIndices[0] = task.taskId;
+        response.transferBalances = new IDarkCoWHook.TransferBalance[](0);
+        response.swapBalances = new IDarkCoWHook.SwapBalance[](0);
+
+        bytes32 messageHash =
+            sm.getMessageHash(task.poolId, response.transferBalances, response.swapBalances);
+        (uint8 v, bytes32 r, bytes32 s) = vm.sign(responder.privateKey, messageHash);
+        response.signature = abi.encodePacked(r, s, v);
+
+        bytes memory publicValues = abi.encode(
+            OrderServiceManager.OrderPublicValues({
+                valid: true,
+                nullifierHash: 0x5289617118f8592ba0ae0fa65e981a3d24ad43104040c8083a7991ab2ac9402e,
+                walletAddress: address(1),
+                amountIn: 5 ether,
+                minAmountOut: 10_000_000_000,
+                treeRoot: TREE_ROOT,
+                nullifierRoot: sm.spentNullifierRoot(),
+                changeCommitment: CHANGE_COMMITMENT,
+                tokenIn: TOKEN_IN,
+                tokenOut: TOKEN_OUT,
+                deadline: 1_735_689_600,
+                currentPrice: 2_050_000_000,
+                blockTimestamp: MARKET_TIMESTAMP
+            })
+        );
+        vm.mockCall(
+            sm.verifier(),
+            abi.encodeCall(
+                ISP1Verifier.verifyProof,
+                (sm.orderProgramVKey(), publicValues, response.signature)
+            ),
+            ""
+        );
+    }
+
+    function respond(Response memory response, bytes32 changeCommitment) internal {
+        vm.prank(responder.addr);
+        sm.respondToBatch(
+            response.tasks,
+            response.referenceTaskIndices,
+            response.transferBalances,
+            response.swapBalances,
+            changeCommitment,
+            response.signature
+        );
+    }
+
+    function testChangeNoteIsCommitted() public {
+        Response memory response = makeResponse();
+
+        vm.expectEmit(address(sm));
+        emit OrderServiceManager.NoteCommitted(CHANGE_COMMITMENT);
+        respond(response, CHANGE_COMMITMENT);
+
+        assertEq(hook.settlements(), 1, "Batch was not settled");
+    }
+
+    function testResponseMustCarryTheProvenChangeNote() public {
+        Response memory response = makeResponse();
+
+        // The proof commits the change note, so public values without it do not verify
+        vm.expectRevert();
+        respond(response, bytes32(0));
+    }
+}
+
 // contract SlashOperator is OrderTaskManagerSetup {
 //     uint256 internal constant INITIAL_BALANCE = 100 ether;
 //     uint256 internal constant DEPOSIT_AMOUNT = 1 ether;
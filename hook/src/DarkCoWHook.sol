@@ -216,6 +216,15 @@ contract DarkCoWHook is BaseHook{
                 }),
                 new bytes(0)
             );
+            // The settlement proof bounds payouts by each swap's output at its
+            // price limit, which only holds if the swap sold its whole input
+            int128 sold = swapBalances[i].zeroForOne
+                ? _swapDelta.amount0()
+                : _swapDelta.amount1();
+            require(
+                sold == swapBalances[i].amountSpecified,
+                "Swap stopped at its price limit"
+            );
             swapDelta = add(swapDelta, _swapDelta);
         }
 
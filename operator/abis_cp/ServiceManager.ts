@@ -336,6 +336,7 @@ export const ServiceManagerABI = [
           },
         ],
       },
+      { name: "changeCommitment", type: "bytes32", internalType: "bytes32" },
       { name: "signature", type: "bytes", internalType: "bytes" },
     ],
     outputs: [],
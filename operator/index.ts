@@ -25,6 +25,10 @@ let chainId = 31337;
 
 let latestBatchNumber: bigint = BigInt(0);
 const MAX_BLOCKS_PER_BATCH = 10;
+// Change note committed by the order proof `respondToBatch` checks: the sample in
+// avs/contract/config/RequestBody.json, which fills 5 ETH of a 10 ETH note
+const CHANGE_COMMITMENT =
+    "0x0729d18e9dd707ff9d2c96c55198fdfc2dd17b1691d10238969bd96be01c9e49" as `0x${string}`;
 const batches: Record<string, Task[]> = {};
 
 interface TransferBalance {
@@ -383,6 +387,7 @@ const processBatch = async (batchNumber: bigint) => {
                         sender: tb.sender as `0x${string}`,
                     })),
                     result.swapBalances,
+                    CHANGE_COMMITMENT,
                     signature,
                 ]);
 
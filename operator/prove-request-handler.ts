@@ -1,9 +1,9 @@
 import { ethers } from 'ethers';
 import axios from 'axios';
 
-// ABI for the ProveRequest event
+// ABI for the ProveRequest event; `provdata` is `OrderServiceManager.ProveRequestData`
 const PROVE_REQUEST_ABI = [
-    "event ProveRequest(uint32 indexed taskIndex, address indexed operator, bytes32 marketCurrentPrice, uint256 marketBlockTimestamp, bytes32 treeRoot, bytes32 nullifierHash, address walletAddress, address tokenIn, address tokenOut, uint256 amountIn, uint256 minAmountOut, uint256 targetPrice, uint256 deadline, bytes32 commitmentNullifier, uint256 balance, bytes32[] siblings, uint32[] indices)"
+    "event ProveRequest(uint32 indexed taskIndex, address indexed operator, tuple(bytes32 marketCurrentPrice, uint256 marketBlockTimestamp, bytes32 treeRoot, bytes32 nullifierHash, address walletAddress, address tokenIn, address tokenOut, uint256 amountIn, uint256 minAmountOut, uint256 targetPrice, uint256 deadline, uint8 side, uint8 baseDecimals, uint8 kind, uint256 triggerPrice, uint8 timeInForce, uint256 validAfter, bytes32 commitmentNullifier, address balanceAsset, uint256 balance, bytes32[] siblings, uint64 leafIndex, uint256 fillAmount, bool hasChangeOrder, tuple(address walletAddress, address tokenIn, address tokenOut, uint256 amountIn, uint256 minAmountOut, uint256 targetPrice, uint256 deadline, uint8 side, uint8 baseDecimals, uint8 kind, uint256 triggerPrice, uint8 timeInForce, uint256 validAfter) changeOrder) provdata)"
 ];
 import { avsServiceManagerAddress } from './utils';
 
@@ -12,6 +12,60 @@ import { avsServiceManagerAddress } from './utils';
 const CONTRACT_ADDRESS = avsServiceManagerAddress; // Replace with actual contract address
 const RPC_URL = 'http://localhost:8545'; // Replace with actual RPC URL
 const PROVE_API_ENDPOINT = 'http://localhost:3000/prove'; // Replace with actual API endpoint
+const JOB_POLL_INTERVAL_MS = 5000;
+
+type JobState = 'queued' | 'executing' | 'proving' | 'done' | 'failed' | 'cancelled';
+
+interface JobResponse {
+    job_id: number;
+    state: JobState;
+    result?: unknown;
+    error?: string;
+}
+
+type OrderKindJson =
+    | 'limit'
+    | { stop_loss: { trigger_price: string } }
+    | { take_profit: { trigger_price: string } };
+
+interface OrderJson {
+    wallet_address: string;
+    token_in: string;
+    token_out: string;
+    amount_in: string;
+    min_amount_out: string;
+    target_price: string;
+    deadline: number;
+    side: 'sell' | 'buy';
+    base_decimals: number;
+    kind: OrderKindJson;
+    time_in_force: 'gtc' | 'ioc' | 'fok';
+    valid_after: number;
+}
+
+// The contract's enum codes, in the order of the server's enums
+const SIDES = ['sell', 'buy'] as const;
+const TIMES_IN_FORCE = ['gtc', 'ioc', 'fok'] as const;
+
+function toOrderKind(kind: number, triggerPrice: string): OrderKindJson {
+    switch (kind) {
+        case 0:
+            return 'limit';
+        case 1:
+            return { stop_loss: { trigger_price: triggerPrice } };
+        case 2:
+            return { take_profit: { trigger_price: triggerPrice } };
+        default:
+            throw new Error(`unknown order kind ${kind}`);
+    }
+}
+
+function fromCode<T>(codes: readonly T[], code: number, name: string): T {
+    if (!(code in codes)) {
+        throw new Error(`unknown ${name} ${code}`);
+    }
+    return codes[code];
+}
 
 interface ProveRequestData {
     market: {
@@ -20,19 +74,33 @@ interface ProveRequestData {
     };
     tree_root: string;
     nullifier_hash: string;
-    order: {
-        wallet_address: string;
-        token_in: string;
-        token_out: string;
-        amount_in: string;
-        min_amount_out: string;
-        target_price: string;
-        deadline: number;
-    };
+    order: OrderJson;
     commitment_nullifier: string;
+    balance_asset: string;
     balance: string;
     siblings: string[];
-    indices: number[];
+    leaf_index: number;
+    fill_amount: string;
+    // Omitted to let the server carry what is left of `order`
+    change_order?: OrderJson;
+}
+
+// An order as the event carries it, in the server's JSON shape
+function toOrderJson(order: any): OrderJson {
+    return {
+        wallet_address: order.walletAddress,
+        token_in: order.tokenIn,
+        token_out: order.tokenOut,
+        amount_in: order.amountIn.toString(),
+        min_amount_out: order.minAmountOut.toString(),
+        target_price: order.targetPrice.toString(),
+        deadline: Number(order.deadline),
+        side: fromCode(SIDES, order.side, 'side'),
+        base_decimals: order.baseDecimals,
+        kind: toOrderKind(order.kind, order.triggerPrice.toString()),
+        time_in_force: fromCode(TIMES_IN_FORCE, order.timeInForce, 'time in force'),
+        valid_after: Number(order.validAfter),
+    };
 }
 
 class ProveRequestHandler {
@@ -50,45 +118,28 @@ class ProveRequestHandler {
         this.contract.on('ProveRequest', async (
             taskIndex: number,
             operator: string,
-            marketCurrentPrice: string,
-            marketBlockTimestamp: number,
-            treeRoot: string,
-            nullifierHash: string,
-            walletAddress: string,
-            tokenIn: string,
-            tokenOut: string,
-            amountIn: string,
-            minAmountOut: string,
-            targetPrice: string,
-            deadline: number,
-            commitmentNullifier: string,
-            balance: string,
-            siblings: string[],
-            indices: number[]
+            provdata: any
         ) => {
             console.log(`Received ProveRequest event for task ${taskIndex} from operator ${operator}`);
 
             try {
                 await this.sendProveRequest({
                     market: {
-                        current_price: marketCurrentPrice,
-                        block_timestamp: marketBlockTimestamp
-                    },
-                    tree_root: treeRoot,
-                    nullifier_hash: nullifierHash,
-                    order: {
-                        wallet_address: walletAddress,
-                        token_in: tokenIn,
-                        token_out: tokenOut,
-                        amount_in: amountIn,
-                        min_amount_out: minAmountOut,
-                        target_price: targetPrice,
-                        deadline: deadline
+                        current_price: ethers.BigNumber.from(provdata.marketCurrentPrice).toString(),
+                        block_timestamp: Number(provdata.marketBlockTimestamp)
                     },
-                    commitment_nullifier: commitmentNullifier,
-                    balance: balance,
-                    siblings: siblings,
-                    indices: indices
+                    tree_root: provdata.treeRoot,
+                    nullifier_hash: provdata.nullifierHash,
+                    order: toOrderJson(provdata),
+                    commitment_nullifier: provdata.commitmentNullifier,
+                    balance_asset: provdata.balanceAsset,
+                    balance: provdata.balance.toString(),
+                    siblings: provdata.siblings,
+                    leaf_index: Number(provdata.leafIndex),
+                    fill_amount: provdata.fillAmount.toString(),
+                    change_order: provdata.hasChangeOrder
+                        ? toOrderJson(provdata.changeOrder)
+                        : undefined
                 });
             } catch (error) {
                 console.error('Error processing ProveRequest:', error);
@@ -100,14 +151,21 @@ class ProveRequestHandler {
         try {
             console.log('Sending POST request to /prove endpoint with data:', JSON.stringify(data, null, 2));
 
-            const response = await axios.post(PROVE_API_ENDPOINT, data, {
+            // The server queues the proof and answers with a job ID straight away
+            const response = await axios.post<JobResponse>(PROVE_API_ENDPOINT, data, {
                 headers: {
                     'Content-Type': 'application/json'
                 },
                 timeout: 30000 // 30 second timeout
             });
+            console.log(`Prove request queued as job ${response.data.job_id}`);
 
-            console.log('Prove request successful:', response.status, response.data);
+            const job = await this.waitForJob(response.data.job_id);
+            if (job.state === 'done') {
+                console.log('Prove request successful:', job.result);
+            } else {
+                console.error(`Prove job ${job.job_id} ${job.state}:`, job.error);
+            }
         } catch (error) {
             console.error('Failed to send prove request:', error);
             if (axios.isAxiosError(error)) {
@@ -117,6 +175,18 @@ class ProveRequestHandler {
         }
     }
 
+    // Polls the job until it is done, failed or cancelled
+    async waitForJob(jobId: number): Promise<JobResponse> {
+        const jobUrl = new URL(`/jobs/${jobId}`, PROVE_API_ENDPOINT).toString();
+        for (;;) {
+            const { data: job } = await axios.get<JobResponse>(jobUrl, { timeout: 30000 });
+            if (job.state === 'done' || job.state === 'failed' || job.state === 'cancelled') {
+                return job;
+            }
+            await new Promise((resolve) => setTimeout(resolve, JOB_POLL_INTERVAL_MS));
+        }
+    }
+
     async stopListening() {
         this.contract.removeAllListeners('ProveRequest');
         console.log('Stopped listening for ProveRequest events');
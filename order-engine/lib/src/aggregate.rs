@@ -0,0 +1,56 @@
+//! Checks the aggregation program makes on the order proofs it verifies.
+//!
+//! The aggregation program verifies compressed proofs of the order program
+//! recursively, which only shows each proof is genuine for its public values.
+//! Those values must also be an enforced order's (a check-mode run commits an
+//! `OrderCheckResult` instead), and, as in a batch, no nullifier may be spent
+//! twice by the same aggregate.
+
+use crate::{BatchRejection, OrderPublicValues, OrderRejection};
+use alloy_sol_types::SolType;
+use std::collections::BTreeSet;
+
+/// Decodes the public values of every order proof and returns them in order
+pub fn try_aggregate_orders(
+    public_values: &[Vec<u8>],
+) -> Result<Vec<OrderPublicValues>, BatchRejection> {
+    if public_values.is_empty() {
+        return Err(BatchRejection {
+            index: 0,
+            reason: OrderRejection::EmptyBatch,
+        });
+    }
+
+    let mut seen = BTreeSet::new();
+    let mut orders = Vec::with_capacity(public_values.len());
+
+    for (index, bytes) in public_values.iter().enumerate() {
+        let reject = |reason| BatchRejection {
+            index: index as u32,
+            reason,
+        };
+
+        // Only the exact encoding of a valid order is accepted
+        let order = OrderPublicValues::abi_decode(bytes)
+            .ok()
+            .filter(|order| order.valid && OrderPublicValues::abi_encode(order) == *bytes)
+            .ok_or_else(|| reject(OrderRejection::InvalidOrderPublicValues))?;
+        if !seen.insert(order.nullifierHash) {
+            return Err(reject(OrderRejection::DuplicateNullifier));
+        }
+        orders.push(order);
+    }
+
+    Ok(orders)
+}
+
+/// The order program's verifying key digest as committed by the aggregation program
+///
+/// `words` is `SP1VerifyingKey::hash_u32()`, the form `verify_sp1_proof` takes.
+pub fn vkey_digest(words: &[u32; 8]) -> [u8; 32] {
+    let mut digest = [0u8; 32];
+    for (i, word) in words.iter().enumerate() {
+        digest[4 * i..4 * i + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
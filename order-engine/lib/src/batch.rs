@@ -0,0 +1,67 @@
+//! Validation of a batch of orders proven in a single program run.
+//!
+//! Every order is checked against the same tree root, nullifier root and
+//! market snapshot. Each nullifier is only shown to be absent from the spent
+//! tree, which says nothing about the rest of the batch, so nullifiers must
+//! also be pairwise distinct.
+
+use crate::{
+    try_verify_order_witness, MarketConditions, OrderPublicValues, OrderRejection, OrderWitness,
+};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// The first order of a batch that failed validation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchRejection {
+    /// Position of the order in the batch, or of the swap in the settlement for
+    /// `OrderRejection::InvalidSwap`
+    pub index: u32,
+    pub reason: OrderRejection,
+}
+
+impl fmt::Display for BatchRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entry = match self.reason {
+            OrderRejection::InvalidSwap => "swap",
+            _ => "order",
+        };
+        write!(f, "{} {}: {}", entry, self.index, self.reason)
+    }
+}
+
+impl std::error::Error for BatchRejection {}
+
+/// Validates every order in the batch and returns their public values in order
+pub fn try_verify_batch(
+    witnesses: &[OrderWitness],
+    market: &MarketConditions,
+    merkle_root: &[u8; 32],
+    nullifier_root: &[u8; 32],
+) -> Result<Vec<OrderPublicValues>, BatchRejection> {
+    if witnesses.is_empty() {
+        return Err(BatchRejection {
+            index: 0,
+            reason: OrderRejection::EmptyBatch,
+        });
+    }
+
+    let mut seen = BTreeSet::new();
+    let mut orders = Vec::with_capacity(witnesses.len());
+
+    for (index, witness) in witnesses.iter().enumerate() {
+        let reject = |reason| BatchRejection {
+            index: index as u32,
+            reason,
+        };
+
+        let public_values = try_verify_order_witness(witness, market, merkle_root, nullifier_root)
+            .map_err(reject)?;
+        if !seen.insert(witness.nullifier_hash) {
+            return Err(reject(OrderRejection::DuplicateNullifier));
+        }
+        orders.push(public_values);
+    }
+
+    Ok(orders)
+}
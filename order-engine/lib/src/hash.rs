@@ -0,0 +1,344 @@
+//! Hash backends for orders, nullifiers, commitments and Merkle nodes.
+//!
+//! The free functions in the crate root hash with [`ActiveHasher`], which is
+//! [`Sha256Hasher`] by default, [`KeccakHasher`] with the `keccak` feature and
+//! [`PoseidonHasher`] with the `poseidon` feature. The guest and every host must
+//! be built with the same backend.
+
+use crate::{OrderData, U256};
+use alloy_primitives::{keccak256, Address, B256};
+use alloy_sol_types::SolValue;
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::{Poseidon, PoseidonHasher as _};
+use sha2::{Digest, Sha256};
+
+/// Hash function used for every hash that ends up in a commitment, nullifier or tree root
+pub trait OrderHasher {
+    /// Hash of the order fields
+    fn hash_order(order: &OrderData) -> [u8; 32];
+
+    /// Public nullifier hash for a private nullifier
+    fn nullifier_hash(nullifier: &[u8; 32]) -> [u8; 32];
+
+    /// Commitment hash over an order hash, nullifier, balance asset and balance (goes in
+    /// the Merkle tree)
+    fn commitment_hash(
+        order_hash: &[u8; 32],
+        nullifier: &[u8; 32],
+        balance_asset: &[u8; 20],
+        balance: U256,
+    ) -> [u8; 32];
+
+    /// Spent-nullifier tree entry: a nullifier hash and a link to the next larger one
+    fn nullifier_leaf(value: &[u8; 32], next_value: &[u8; 32], next_index: u64) -> [u8; 32];
+
+    /// Merkle leaf for a commitment hash, tagged apart from internal nodes
+    fn merkle_leaf(commitment_hash: &[u8; 32]) -> [u8; 32];
+
+    /// Parent of two Merkle nodes
+    fn merkle_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32];
+
+    /// Deterministic nullifier derived from a user secret and an order context
+    fn order_nullifier(user_secret: &[u8; 32], order_context: &[u8; 32]) -> [u8; 32];
+
+    /// Whether a 32-byte input hashes as itself rather than as some other word
+    ///
+    /// Byte-oriented hashes read every word exactly.
+    fn is_canonical(_word: &[u8; 32]) -> bool {
+        true
+    }
+}
+
+#[cfg(all(feature = "keccak", feature = "poseidon"))]
+compile_error!("features `keccak` and `poseidon` select different hash backends");
+
+#[cfg(not(any(feature = "keccak", feature = "poseidon")))]
+pub type ActiveHasher = Sha256Hasher;
+
+#[cfg(feature = "keccak")]
+pub type ActiveHasher = KeccakHasher;
+
+#[cfg(feature = "poseidon")]
+pub type ActiveHasher = PoseidonHasher;
+
+/// Encodes an integer as a 32-byte big-endian word, the same as `abi.encode(uint256)`
+pub(crate) fn uint_word(value: U256) -> [u8; 32] {
+    value.to_be_bytes::<32>()
+}
+
+/// SHA-256 over ASCII domain tags followed by the raw fields
+pub struct Sha256Hasher;
+
+impl OrderHasher for Sha256Hasher {
+    /// Every integer field is hashed as a 32-byte big-endian word so the encoding is
+    /// canonical regardless of the host's integer widths.
+    fn hash_order(order: &OrderData) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(order.wallet_address);
+        hasher.update(order.token_in);
+        hasher.update(order.token_out);
+        hasher.update(uint_word(order.amount_in));
+        hasher.update(uint_word(order.min_amount_out));
+        hasher.update(uint_word(order.target_price));
+        hasher.update(uint_word(U256::from(order.deadline)));
+        hasher.update(uint_word(U256::from(order.side as u8)));
+        hasher.update(uint_word(U256::from(order.base_decimals)));
+        hasher.update(uint_word(U256::from(order.kind.code())));
+        hasher.update(uint_word(order.kind.trigger_price()));
+        hasher.update(uint_word(U256::from(order.time_in_force as u8)));
+        hasher.update(uint_word(U256::from(order.valid_after)));
+        hasher.finalize().into()
+    }
+
+    fn nullifier_hash(nullifier: &[u8; 32]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(b"NULLIFIER_HASH"); // Domain separation
+        hasher.update(nullifier);
+        hasher.finalize().into()
+    }
+
+    fn commitment_hash(
+        order_hash: &[u8; 32],
+        nullifier: &[u8; 32],
+        balance_asset: &[u8; 20],
+        balance: U256,
+    ) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(b"COMMITMENT_HASH"); // Domain separation
+        hasher.update(order_hash);
+        hasher.update(nullifier);
+        hasher.update(balance_asset);
+        hasher.update(uint_word(balance));
+        hasher.finalize().into()
+    }
+
+    fn nullifier_leaf(value: &[u8; 32], next_value: &[u8; 32], next_index: u64) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(b"NULLIFIER_LEAF"); // Domain separation
+        hasher.update(value);
+        hasher.update(next_value);
+        hasher.update(uint_word(U256::from(next_index)));
+        hasher.finalize().into()
+    }
+
+    fn merkle_leaf(commitment_hash: &[u8; 32]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(b"MERKLE_LEAF"); // Domain separation
+        hasher.update(commitment_hash);
+        hasher.finalize().into()
+    }
+
+    fn merkle_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(b"MERKLE_NODE"); // Domain separation
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize().into()
+    }
+
+    fn order_nullifier(user_secret: &[u8; 32], order_context: &[u8; 32]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(b"ORDER_NULLIFIER"); // Domain separation
+        hasher.update(user_secret);
+        hasher.update(order_context);
+        hasher.finalize().into()
+    }
+}
+
+/// keccak256 over `abi.encode`d fields, recomputable on-chain
+///
+/// Domain tags are encoded as `bytes32("TAG")`, so for example a Merkle node is
+/// `keccak256(abi.encode(bytes32("MERKLE_NODE"), left, right))` in Solidity.
+pub struct KeccakHasher;
+
+/// A domain tag as Solidity's `bytes32("TAG")`: left-aligned and zero padded
+fn domain(tag: &[u8]) -> B256 {
+    B256::right_padding_from(tag)
+}
+
+impl OrderHasher for KeccakHasher {
+    /// `keccak256(abi.encode(walletAddress, tokenIn, tokenOut, amountIn, minAmountOut,
+    /// targetPrice, deadline, side, baseDecimals, kind, triggerPrice, timeInForce,
+    /// validAfter))`
+    fn hash_order(order: &OrderData) -> [u8; 32] {
+        let encoded = (
+            Address::from(order.wallet_address),
+            Address::from(order.token_in),
+            Address::from(order.token_out),
+            order.amount_in,
+            order.min_amount_out,
+            order.target_price,
+            U256::from(order.deadline),
+            U256::from(order.side as u8),
+            U256::from(order.base_decimals),
+            U256::from(order.kind.code()),
+            order.kind.trigger_price(),
+            U256::from(order.time_in_force as u8),
+            U256::from(order.valid_after),
+        )
+            .abi_encode_params();
+        keccak256(encoded).0
+    }
+
+    fn nullifier_hash(nullifier: &[u8; 32]) -> [u8; 32] {
+        let encoded = (domain(b"NULLIFIER_HASH"), B256::from(*nullifier)).abi_encode_params();
+        keccak256(encoded).0
+    }
+
+    fn commitment_hash(
+        order_hash: &[u8; 32],
+        nullifier: &[u8; 32],
+        balance_asset: &[u8; 20],
+        balance: U256,
+    ) -> [u8; 32] {
+        let encoded = (
+            domain(b"COMMITMENT_HASH"),
+            B256::from(*order_hash),
+            B256::from(*nullifier),
+            Address::from(*balance_asset),
+            balance,
+        )
+            .abi_encode_params();
+        keccak256(encoded).0
+    }
+
+    fn nullifier_leaf(value: &[u8; 32], next_value: &[u8; 32], next_index: u64) -> [u8; 32] {
+        let encoded = (
+            domain(b"NULLIFIER_LEAF"),
+            B256::from(*value),
+            B256::from(*next_value),
+            U256::from(next_index),
+        )
+            .abi_encode_params();
+        keccak256(encoded).0
+    }
+
+    fn merkle_leaf(commitment_hash: &[u8; 32]) -> [u8; 32] {
+        let encoded = (domain(b"MERKLE_LEAF"), B256::from(*commitment_hash)).abi_encode_params();
+        keccak256(encoded).0
+    }
+
+    fn merkle_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        let encoded = (
+            domain(b"MERKLE_NODE"),
+            B256::from(*left),
+            B256::from(*right),
+        )
+            .abi_encode_params();
+        keccak256(encoded).0
+    }
+
+    fn order_nullifier(user_secret: &[u8; 32], order_context: &[u8; 32]) -> [u8; 32] {
+        let encoded = (
+            domain(b"ORDER_NULLIFIER"),
+            B256::from(*user_secret),
+            B256::from(*order_context),
+        )
+            .abi_encode_params();
+        keccak256(encoded).0
+    }
+}
+
+/// Circom-compatible Poseidon over the BN254 scalar field
+///
+/// Every 32-byte input is read as a big-endian integer and reduced modulo the
+/// field order, and the domain tag goes in the capacity element. A word at or
+/// above the ~2^254 modulus would hash the same as a smaller one, so
+/// [`is_canonical`](OrderHasher::is_canonical) rejects it and the witness checks
+/// refuse such values.
+pub struct PoseidonHasher;
+
+fn field(bytes: &[u8]) -> Fr {
+    Fr::from_be_bytes_mod_order(bytes)
+}
+
+fn poseidon(tag: &[u8], inputs: &[Fr]) -> [u8; 32] {
+    let mut poseidon = Poseidon::<Fr>::with_domain_tag_circom(inputs.len(), field(tag))
+        .expect("Poseidon supports up to 12 inputs");
+    let hash = poseidon
+        .hash(inputs)
+        .expect("input count matches the Poseidon width");
+    hash.into_bigint()
+        .to_bytes_be()
+        .try_into()
+        .expect("BN254 field elements are 32 bytes")
+}
+
+impl OrderHasher for PoseidonHasher {
+    /// The kind, trigger, time in force and start time are hashed on their own
+    /// and enter the order hash as one input, keeping it within the 12-input width.
+    fn hash_order(order: &OrderData) -> [u8; 32] {
+        let terms = poseidon(
+            b"ORDER_TERMS",
+            &[
+                Fr::from(order.kind.code()),
+                field(&uint_word(order.kind.trigger_price())),
+                Fr::from(order.time_in_force as u64),
+                Fr::from(order.valid_after),
+            ],
+        );
+        poseidon(
+            b"ORDER_HASH",
+            &[
+                field(&order.wallet_address),
+                field(&order.token_in),
+                field(&order.token_out),
+                field(&uint_word(order.amount_in)),
+                field(&uint_word(order.min_amount_out)),
+                field(&uint_word(order.target_price)),
+                Fr::from(order.deadline),
+                Fr::from(order.side as u64),
+                Fr::from(order.base_decimals),
+                field(&terms),
+            ],
+        )
+    }
+
+    fn nullifier_hash(nullifier: &[u8; 32]) -> [u8; 32] {
+        poseidon(b"NULLIFIER_HASH", &[field(nullifier)])
+    }
+
+    fn commitment_hash(
+        order_hash: &[u8; 32],
+        nullifier: &[u8; 32],
+        balance_asset: &[u8; 20],
+        balance: U256,
+    ) -> [u8; 32] {
+        poseidon(
+            b"COMMITMENT_HASH",
+            &[
+                field(order_hash),
+                field(nullifier),
+                field(balance_asset),
+                field(&uint_word(balance)),
+            ],
+        )
+    }
+
+    fn nullifier_leaf(value: &[u8; 32], next_value: &[u8; 32], next_index: u64) -> [u8; 32] {
+        poseidon(
+            b"NULLIFIER_LEAF",
+            &[field(value), field(next_value), Fr::from(next_index)],
+        )
+    }
+
+    fn merkle_leaf(commitment_hash: &[u8; 32]) -> [u8; 32] {
+        poseidon(b"MERKLE_LEAF", &[field(commitment_hash)])
+    }
+
+    fn merkle_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        poseidon(b"MERKLE_NODE", &[field(left), field(right)])
+    }
+
+    fn order_nullifier(user_secret: &[u8; 32], order_context: &[u8; 32]) -> [u8; 32] {
+        poseidon(
+            b"ORDER_NULLIFIER",
+            &[field(user_secret), field(order_context)],
+        )
+    }
+
+    fn is_canonical(word: &[u8; 32]) -> bool {
+        field(word).into_bigint().to_bytes_be() == word
+    }
+}
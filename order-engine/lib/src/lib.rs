@@ -1,23 +1,382 @@
+pub use alloy_primitives::U256;
 use alloy_sol_types::sol;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::fmt;
+
+pub mod aggregate;
+pub mod batch;
+pub mod hash;
+pub mod merkle;
+pub mod nullifier;
+pub mod price;
+pub mod settlement;
+
+pub use aggregate::{try_aggregate_orders, vkey_digest};
+pub use batch::{try_verify_batch, BatchRejection};
+use hash::uint_word;
+pub use hash::{ActiveHasher, KeccakHasher, OrderHasher, PoseidonHasher, Sha256Hasher};
+pub use merkle::{IncrementalMerkleTree, MerkleTreeError, ROOT_HISTORY_SIZE, TREE_DEPTH};
+pub use nullifier::{
+    NullifierInsertionProof, NullifierLeaf, NullifierNonMembershipProof, NullifierTree,
+};
+pub use price::{base_amount, expected_amount_out, price_scale, quote_amount};
+pub use settlement::{settlement_hash, try_verify_settlement};
+
+sol! {
+    /// The public values committed by the order program, ABI-encoded so that
+    /// `OrderServiceManager.verifyOrderProof` can decode them on-chain.
+    ///
+    /// The tree root and market snapshot are committed alongside the order so a
+    /// verifier can check them against known roots and oracle prices. The
+    /// nullifier root is the spent-nullifier tree the nullifier was shown to be
+    /// absent from.
+    ///
+    /// `amountIn` and `minAmountOut` describe this fill, which may be part of the
+    /// order. `changeCommitment` is the note holding the rest of the spent balance,
+    /// to be appended to the commitment tree (zero if nothing is left).
+    struct OrderPublicValues {
+        bool valid;
+        bytes32 nullifierHash;
+        address walletAddress;
+        uint256 amountIn;
+        uint256 minAmountOut;
+        bytes32 treeRoot;
+        bytes32 nullifierRoot;
+        bytes32 changeCommitment;
+        address tokenIn;
+        address tokenOut;
+        uint64 deadline;
+        uint256 currentPrice;
+        uint64 blockTimestamp;
+    }
+
+    /// The only public value committed by the order program in `ValidationMode::Check`.
+    ///
+    /// Its encoding never decodes as `OrderPublicValues`, so a check-mode proof can't be
+    /// passed off as a proof of a valid order.
+    struct OrderCheckResult {
+        uint8 reason;
+    }
+
+    /// The public values committed by the batch order program: one entry per order,
+    /// all sharing the same tree root, nullifier root and market snapshot.
+    struct BatchPublicValues {
+        OrderPublicValues[] orders;
+    }
+
+    /// The only public value committed by the batch order and settlement programs in
+    /// `ValidationMode::Check`: the first rejected entry and why (`reason` 0 means the
+    /// whole batch passed).
+    struct BatchCheckResult {
+        uint32 index;
+        uint8 reason;
+    }
+
+    /// The public values committed by the aggregation program: the order program's
+    /// verifying key digest (`vk.hash_u32()` as big-endian words) and the public
+    /// values of every order proof it verified.
+    ///
+    /// Each `OrderPublicValues` is a static tuple, so `orders` encodes as the order
+    /// proofs' public values concatenated behind a length word.
+    struct AggregatePublicValues {
+        bytes32 orderVKeyDigest;
+        OrderPublicValues[] orders;
+    }
+
+    /// Mirrors `DarkCoWHook.TransferBalance`: the hook pays `amount` of `currency`
+    /// out of its claims to `sender` (the receiving wallet, despite the name).
+    struct TransferBalance {
+        uint256 amount;
+        address currency;
+        address sender;
+    }
+
+    /// Mirrors `DarkCoWHook.SwapBalance`: one swap the hook makes against its pool.
+    /// A negative `amountSpecified` is an exact-input swap.
+    struct SwapBalance {
+        int256 amountSpecified;
+        bool zeroForOne;
+        uint160 sqrtPriceLimitX96;
+    }
+
+    /// The settlement proposed for a set of matched orders, in the pool given by
+    /// its two currencies; the settlement program reads it ABI-encoded.
+    struct Settlement {
+        address currency0;
+        address currency1;
+        TransferBalance[] transferBalances;
+        SwapBalance[] swapBalances;
+    }
+
+    /// The public values committed by the settlement program.
+    ///
+    /// `settlementHash` is `keccak256(abi.encode(transferBalances, swapBalances))`, so
+    /// the arrays passed to `DarkCoWHook.settleBalances` can be checked against it.
+    /// `newNullifierRoot` is the spent-nullifier root once every order's nullifier
+    /// is inserted, which the contract moves to after settling.
+    struct SettlementPublicValues {
+        bytes32 settlementHash;
+        address currency0;
+        address currency1;
+        bytes32 newNullifierRoot;
+        OrderPublicValues[] orders;
+    }
+}
+
+/// How the order program treats an order that fails validation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ValidationMode {
+    /// Panic on any failed check, so no proof can exist for an invalid order
+    Enforce,
+    /// Commit only an `OrderCheckResult`; used for execute-only runs to explain a rejection
+    Check,
+}
 
+/// Why an order was rejected by validation
+///
+/// The discriminant is the reason code committed in `OrderCheckResult`
+/// (0 means the order passed every check).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum OrderRejection {
+    DeadlineExpired = 1,
+    PriceBelowTarget = 2,
+    InsufficientBalance = 3,
+    CommitmentMismatch = 4,
+    NullifierMismatch = 5,
+    MerklePathLengthMismatch = 6,
+    MerkleRootMismatch = 7,
+    OrderHashMismatch = 8,
+    LeafIndexOutOfRange = 9,
+    NullifierSpent = 10,
+    NullifierRootMismatch = 11,
+    DuplicateNullifier = 12,
+    EmptyBatch = 13,
+    TransferCountMismatch = 14,
+    TransferRecipientMismatch = 15,
+    TransferCurrencyMismatch = 16,
+    FillBelowMinimum = 17,
+    FillBelowTargetPrice = 18,
+    InvalidSwap = 19,
+    FlowNotConserved = 20,
+    InvalidOrderPublicValues = 21,
+    InvalidFillAmount = 22,
+    BalanceAssetMismatch = 23,
+    PriceAboveTarget = 24,
+    MinAmountOutUnreachable = 25,
+    InvalidDecimals = 26,
+    TriggerNotReached = 27,
+    OrderNotYetValid = 28,
+    FillOrKillPartial = 29,
+    NonCanonicalValue = 30,
+    ChangeOrderMissing = 31,
+    NullifierInsertionMismatch = 32,
+}
+
+impl OrderRejection {
+    /// Reason code committed by the order program
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// Decodes a reason code committed by the order program
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(Self::DeadlineExpired),
+            2 => Some(Self::PriceBelowTarget),
+            3 => Some(Self::InsufficientBalance),
+            4 => Some(Self::CommitmentMismatch),
+            5 => Some(Self::NullifierMismatch),
+            6 => Some(Self::MerklePathLengthMismatch),
+            7 => Some(Self::MerkleRootMismatch),
+            8 => Some(Self::OrderHashMismatch),
+            9 => Some(Self::LeafIndexOutOfRange),
+            10 => Some(Self::NullifierSpent),
+            11 => Some(Self::NullifierRootMismatch),
+            12 => Some(Self::DuplicateNullifier),
+            13 => Some(Self::EmptyBatch),
+            14 => Some(Self::TransferCountMismatch),
+            15 => Some(Self::TransferRecipientMismatch),
+            16 => Some(Self::TransferCurrencyMismatch),
+            17 => Some(Self::FillBelowMinimum),
+            18 => Some(Self::FillBelowTargetPrice),
+            19 => Some(Self::InvalidSwap),
+            20 => Some(Self::FlowNotConserved),
+            21 => Some(Self::InvalidOrderPublicValues),
+            22 => Some(Self::InvalidFillAmount),
+            23 => Some(Self::BalanceAssetMismatch),
+            24 => Some(Self::PriceAboveTarget),
+            25 => Some(Self::MinAmountOutUnreachable),
+            26 => Some(Self::InvalidDecimals),
+            27 => Some(Self::TriggerNotReached),
+            28 => Some(Self::OrderNotYetValid),
+            29 => Some(Self::FillOrKillPartial),
+            30 => Some(Self::NonCanonicalValue),
+            31 => Some(Self::ChangeOrderMissing),
+            32 => Some(Self::NullifierInsertionMismatch),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for OrderRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            Self::DeadlineExpired => "order deadline has expired",
+            Self::PriceBelowTarget => "market price is below the order's target price",
+            Self::InsufficientBalance => "committed balance is below the order amount",
+            Self::CommitmentMismatch => "commitment hash does not match the order",
+            Self::NullifierMismatch => "nullifier hash does not match the nullifier",
+            Self::MerklePathLengthMismatch => "Merkle path length does not match the tree depth",
+            Self::MerkleRootMismatch => "Merkle path does not lead to the expected root",
+            Self::OrderHashMismatch => "order hash does not match the expected hash",
+            Self::LeafIndexOutOfRange => "leaf index does not fit in the tree depth",
+            Self::NullifierSpent => "nullifier has already been spent",
+            Self::NullifierRootMismatch => "nullifier witness does not lead to the nullifier root",
+            Self::DuplicateNullifier => "nullifier appears more than once in the batch",
+            Self::EmptyBatch => "batch contains no orders",
+            Self::TransferCountMismatch => "settlement does not pay exactly one transfer per order",
+            Self::TransferRecipientMismatch => "transfer does not pay the order's wallet",
+            Self::TransferCurrencyMismatch => "transfer is not in the order's output token",
+            Self::FillBelowMinimum => "transfer is below the order's minimum amount out",
+            Self::FillBelowTargetPrice => "transfer is worse than the order's target price",
+            Self::InvalidSwap => {
+                "swap is not a price-limited exact-input sale in the settlement's direction"
+            }
+            Self::FlowNotConserved => {
+                "settlement pays out a token in another amount than it brings in"
+            }
+            Self::InvalidOrderPublicValues => "proof public values are not those of a valid order",
+            Self::InvalidFillAmount => "fill amount is zero or exceeds the order amount",
+            Self::BalanceAssetMismatch => "committed balance is not in the order's input token",
+            Self::PriceAboveTarget => "market price is above the order's target price",
+            Self::MinAmountOutUnreachable => {
+                "order input at the market price is below its minimum amount out"
+            }
+            Self::InvalidDecimals => "base token decimals do not give a 256-bit price scale",
+            Self::TriggerNotReached => "market price has not reached the order's trigger price",
+            Self::OrderNotYetValid => "order is not valid before its start time",
+            Self::FillOrKillPartial => "fill-or-kill order must be filled in full",
+            Self::NonCanonicalValue => "value is not read exactly by the hash backend",
+            Self::ChangeOrderMissing => "order closes with balance left over but no change order",
+            Self::NullifierInsertionMismatch => {
+                "nullifier insertion does not go into an empty slot of the nullifier tree"
+            }
+        };
+        f.write_str(msg)
+    }
+}
 
+impl std::error::Error for OrderRejection {}
+
+/// Which way an order trades the pair the market price is quoted for
+///
+/// `MarketConditions::current_price` is the price of the base token in the
+/// quote token (e.g. USDC per ETH), and `target_price` is quoted the same way
+/// for both sides, so the side decides which way the price check goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderSide {
+    /// Sells the base token (`token_in`) for the quote token: `target_price`
+    /// is the lowest price the order accepts
+    #[default]
+    Sell = 0,
+    /// Buys the base token (`token_out`) with the quote token: `target_price`
+    /// is the highest price the order accepts
+    Buy = 1,
+}
+
+/// When an order becomes executable
+///
+/// Every kind still has to clear `target_price` for its side; stop and
+/// take-profit orders additionally wait for the market to cross a trigger, so
+/// they act as stop-limit and take-profit-limit orders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderKind {
+    /// Executable whenever the price clears `target_price`
+    #[default]
+    Limit,
+    /// Executable once the price has moved against the order to
+    /// `trigger_price`: at or below it for a sell, at or above it for a buy
+    StopLoss { trigger_price: U256 },
+    /// Executable once the price has moved in the order's favour to
+    /// `trigger_price`: at or above it for a sell, at or below it for a buy
+    TakeProfit { trigger_price: U256 },
+}
+
+impl OrderKind {
+    /// Kind code bound into the order hash
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Limit => 0,
+            Self::StopLoss { .. } => 1,
+            Self::TakeProfit { .. } => 2,
+        }
+    }
 
+    /// Trigger price bound into the order hash; zero for a limit order
+    pub fn trigger_price(&self) -> U256 {
+        match self {
+            Self::Limit => U256::ZERO,
+            Self::StopLoss { trigger_price } | Self::TakeProfit { trigger_price } => *trigger_price,
+        }
+    }
+
+    /// Whether the market price has reached the trigger for an order on `side`
+    pub fn is_triggered(&self, side: OrderSide, current_price: U256) -> bool {
+        match (self, side) {
+            (Self::Limit, _) => true,
+            (Self::StopLoss { trigger_price }, OrderSide::Sell)
+            | (Self::TakeProfit { trigger_price }, OrderSide::Buy) => {
+                current_price <= *trigger_price
+            }
+            (Self::StopLoss { trigger_price }, OrderSide::Buy)
+            | (Self::TakeProfit { trigger_price }, OrderSide::Sell) => {
+                current_price >= *trigger_price
+            }
+        }
+    }
+}
+
+/// How long an order stays open and how much of it must fill at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeInForce {
+    /// Good 'til cancelled: open until the deadline, and a partial fill leaves
+    /// the rest of the order open in the change note
+    #[default]
+    Gtc = 0,
+    /// Immediate or cancel: fills as much as it can in one proof, and the rest
+    /// of the order is cancelled
+    Ioc = 1,
+    /// Fill or kill: fills the whole order in one proof or not at all
+    Fok = 2,
+}
+
+/// Token amounts and prices are 256-bit, matching the `uint256` fields on-chain
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderData {
     pub wallet_address: [u8; 20],
     pub token_in: [u8; 20],
     pub token_out: [u8; 20],
-    pub amount_in: u64,
-    pub min_amount_out: u64,
-    pub target_price: u64,
+    pub amount_in: U256,
+    pub min_amount_out: U256,
+    pub target_price: U256,
     pub deadline: u64,
+    pub side: OrderSide,
+    pub base_decimals: u8, // Base token decimals; sets the price scale
+    pub kind: OrderKind,
+    pub time_in_force: TimeInForce,
+    pub valid_after: u64, // Earliest block timestamp the order can execute at
 }
 
+/// Market snapshot an order is checked against; `current_price` is the base
+/// token's price in the quote token, see [`OrderSide`]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MarketConditions {
-    pub current_price: u64,
+    pub current_price: U256,
     pub block_timestamp: u64,
 }
 
@@ -25,8 +384,9 @@ pub struct MarketConditions {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderCommitment {
     pub order_data: OrderData,
-    pub nullifier: [u8; 32], // Private nullifier secret
-    pub balance: u64,        // User's private balance
+    pub nullifier: [u8; 32],     // Private nullifier secret
+    pub balance_asset: [u8; 20], // Token the balance is held in
+    pub balance: U256,           // User's private balance
 }
 
 /// Public nullifier data for preventing double-spending
@@ -36,61 +396,101 @@ pub struct NullifierData {
     pub commitment_hash: [u8; 32], // Public commitment hash (goes in Merkle tree)
 }
 
+/// Everything needed to prove one order against a tree root and nullifier root
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderWitness {
+    pub nullifier_hash: [u8; 32], // Public nullifier hash the proof is for
+    pub order: OrderData,
+    pub nullifier: [u8; 32],     // Private nullifier secret
+    pub balance_asset: [u8; 20], // Private token the balance is held in
+    pub balance: U256,           // Private balance
+    pub siblings: Vec<[u8; 32]>,
+    pub leaf_index: u64,
+    pub non_membership: NullifierNonMembershipProof,
+    pub fill_amount: U256,          // Part of `amount_in` filled by this proof
+    pub change_nullifier: [u8; 32], // Private nullifier secret of the change note
+    /// Private order for the change note in place of what is left of `order`;
+    /// needed once `order` closes with balance left over
+    #[serde(default)]
+    pub change_order: Option<OrderData>,
+}
+
 /// Validates order conditions including market and time constraints
 pub fn validate_order(
     order: &OrderData,
     market: &MarketConditions,
     expected_hash: &[u8; 32],
 ) -> bool {
+    try_validate_order(order, market, expected_hash).is_ok()
+}
+
+/// Like [`validate_order`], but reports which condition failed
+pub fn try_validate_order(
+    order: &OrderData,
+    market: &MarketConditions,
+    expected_hash: &[u8; 32],
+) -> Result<(), OrderRejection> {
     if market.block_timestamp > order.deadline {
-        return false;
+        return Err(OrderRejection::DeadlineExpired);
     }
 
-    if market.current_price < order.target_price {
-        return false;
+    if market.block_timestamp < order.valid_after {
+        return Err(OrderRejection::OrderNotYetValid);
+    }
+
+    if !order.kind.is_triggered(order.side, market.current_price) {
+        return Err(OrderRejection::TriggerNotReached);
+    }
+
+    match order.side {
+        OrderSide::Sell if market.current_price < order.target_price => {
+            return Err(OrderRejection::PriceBelowTarget);
+        }
+        OrderSide::Buy if market.current_price > order.target_price => {
+            return Err(OrderRejection::PriceAboveTarget);
+        }
+        _ => {}
+    }
+
+    // The whole input, converted at the market price and rounded down, must
+    // cover the minimum output; an amount too large for 256 bits covers any
+    let scale = price_scale(order.base_decimals).ok_or(OrderRejection::InvalidDecimals)?;
+    if expected_amount_out(order, market.current_price, scale)
+        .is_some_and(|amount_out| amount_out < order.min_amount_out)
+    {
+        return Err(OrderRejection::MinAmountOutUnreachable);
     }
 
     let computed_hash = hash_order(order);
-    computed_hash == *expected_hash
+    if computed_hash != *expected_hash {
+        return Err(OrderRejection::OrderHashMismatch);
+    }
+
+    Ok(())
 }
 
 /// Computes deterministic hash of order data
 pub fn hash_order(order: &OrderData) -> [u8; 32] {
-    let mut hasher = Sha256::new();
-    hasher.update(&order.wallet_address);
-    hasher.update(&order.token_in);
-    hasher.update(&order.token_out);
-    hasher.update(&order.amount_in.to_le_bytes());
-    hasher.update(&order.min_amount_out.to_le_bytes());
-    hasher.update(&order.target_price.to_le_bytes());
-    hasher.update(&order.deadline.to_le_bytes());
-    hasher.finalize().into()
+    ActiveHasher::hash_order(order)
 }
 
 /// Computes nullifier hash from private nullifier (prevents double-spending)
 pub fn compute_nullifier_hash(nullifier: &[u8; 32]) -> [u8; 32] {
-    let mut hasher = Sha256::new();
-    hasher.update(b"NULLIFIER_HASH"); // Domain separation
-    hasher.update(nullifier);
-    hasher.finalize().into()
+    ActiveHasher::nullifier_hash(nullifier)
 }
 
-/// Computes commitment hash from order, nullifier, and balance (goes in Merkle tree)
-pub fn compute_commitment_hash(order: &OrderData, nullifier: &[u8; 32], balance: u64) -> [u8; 32] {
-    let mut hasher = Sha256::new();
-    hasher.update(b"COMMITMENT_HASH"); // Domain separation
-
-    // Hash order data
-    let order_hash = hash_order(order);
-    hasher.update(&order_hash);
-
-    // Hash nullifier
-    hasher.update(nullifier);
-
-    // Hash balance
-    hasher.update(&balance.to_le_bytes());
-
-    hasher.finalize().into()
+/// Computes commitment hash from order, nullifier, balance asset and balance (goes in
+/// Merkle tree)
+///
+/// The asset is bound alongside the amount, so a note is only ever spendable as the
+/// token it was committed in.
+pub fn compute_commitment_hash(
+    order: &OrderData,
+    nullifier: &[u8; 32],
+    balance_asset: &[u8; 20],
+    balance: U256,
+) -> [u8; 32] {
+    ActiveHasher::commitment_hash(&hash_order(order), nullifier, balance_asset, balance)
 }
 
 /// Verifies that the user knows the nullifier for their commitment
@@ -99,68 +499,218 @@ pub fn verify_nullifier_knowledge(
     expected_commitment_hash: &[u8; 32],
     expected_nullifier_hash: &[u8; 32],
 ) -> bool {
+    try_verify_nullifier_knowledge(
+        commitment,
+        expected_commitment_hash,
+        expected_nullifier_hash,
+    )
+    .is_ok()
+}
+
+/// Like [`verify_nullifier_knowledge`], but reports which hash did not match
+pub fn try_verify_nullifier_knowledge(
+    commitment: &OrderCommitment,
+    expected_commitment_hash: &[u8; 32],
+    expected_nullifier_hash: &[u8; 32],
+) -> Result<(), OrderRejection> {
+    ensure_canonical(&order_words(&commitment.order_data))?;
+    ensure_canonical(&[commitment.nullifier, uint_word(commitment.balance)])?;
+
     // Verify commitment hash matches
     let computed_commitment = compute_commitment_hash(
         &commitment.order_data,
         &commitment.nullifier,
+        &commitment.balance_asset,
         commitment.balance,
     );
 
     if computed_commitment != *expected_commitment_hash {
-        return false;
+        return Err(OrderRejection::CommitmentMismatch);
     }
 
     // Verify nullifier hash matches
     let computed_nullifier_hash = compute_nullifier_hash(&commitment.nullifier);
     if computed_nullifier_hash != *expected_nullifier_hash {
-        return false;
+        return Err(OrderRejection::NullifierMismatch);
     }
 
-    true
+    Ok(())
 }
 
 /// Verifies Merkle proof for commitment hash (not individual balance)
+///
+/// The path must have exactly [`TREE_DEPTH`] siblings, so every proof has the
+/// same size whatever the number of leaves. Bit `i` of `leaf_index` is 0 when
+/// the node at level `i` is a left child, and no bit at or above `TREE_DEPTH`
+/// may be set. The commitment is hashed as a leaf before walking the path, so
+/// an internal node can never be presented as a commitment.
 pub fn verify_commitment_merkle_proof(
     commitment_hash: &[u8; 32],
-    siblings: &Vec<[u8; 32]>,
-    indices: &Vec<u8>,
+    siblings: &[[u8; 32]],
+    leaf_index: u64,
     expected_root: &[u8; 32],
 ) -> bool {
-    if siblings.len() != indices.len() {
-        return false;
+    try_verify_commitment_merkle_proof(commitment_hash, siblings, leaf_index, expected_root).is_ok()
+}
+
+/// Like [`verify_commitment_merkle_proof`], but reports why the path was rejected
+pub fn try_verify_commitment_merkle_proof(
+    commitment_hash: &[u8; 32],
+    siblings: &[[u8; 32]],
+    leaf_index: u64,
+    expected_root: &[u8; 32],
+) -> Result<(), OrderRejection> {
+    if &merkle_path_root(commitment_hash, siblings, leaf_index)? != expected_root {
+        return Err(OrderRejection::MerkleRootMismatch);
     }
 
-    let mut current_hash = *commitment_hash;
+    Ok(())
+}
 
-    // Traverse up the tree
-    for (i, sibling) in siblings.iter().enumerate() {
-        let mut hasher = Sha256::new();
-        hasher.update(b"MERKLE_NODE"); // Domain separation
+/// Verifies that a nullifier hash is not in the spent-nullifier tree with the given root
+pub fn verify_nullifier_non_membership(
+    nullifier_hash: &[u8; 32],
+    proof: &NullifierNonMembershipProof,
+    nullifier_root: &[u8; 32],
+) -> bool {
+    try_verify_nullifier_non_membership(nullifier_hash, proof, nullifier_root).is_ok()
+}
 
-        if indices[i] == 0 {
+/// Like [`verify_nullifier_non_membership`], but reports why the witness was rejected
+pub fn try_verify_nullifier_non_membership(
+    nullifier_hash: &[u8; 32],
+    proof: &NullifierNonMembershipProof,
+    nullifier_root: &[u8; 32],
+) -> Result<(), OrderRejection> {
+    // A range bound the hash reads as a smaller value would widen the range
+    let low_leaf = &proof.low_leaf;
+    ensure_canonical(&[*nullifier_hash, low_leaf.value, low_leaf.next_value])?;
+
+    // The low leaf's range must contain the hash, or it is spent (or the witness is bogus)
+    if !proof.low_leaf.covers(nullifier_hash) {
+        return Err(OrderRejection::NullifierSpent);
+    }
+
+    let root = merkle_path_root(
+        &proof.low_leaf.hash(),
+        &proof.siblings,
+        proof.low_leaf_index,
+    )?;
+    if &root != nullifier_root {
+        return Err(OrderRejection::NullifierRootMismatch);
+    }
+
+    Ok(())
+}
+
+/// Verifies that inserting a nullifier hash into the spent-nullifier tree with
+/// the given root is sound, and returns the root after it
+///
+/// The low leaf must cover the hash under the old root. Relinked to the new
+/// leaf, it gives an intermediate root, under which the new leaf's slot must
+/// still be empty; the new leaf then takes over the low leaf's old link.
+pub fn try_verify_nullifier_insertion(
+    nullifier_hash: &[u8; 32],
+    proof: &NullifierInsertionProof,
+    nullifier_root: &[u8; 32],
+) -> Result<[u8; 32], OrderRejection> {
+    let non_membership = &proof.non_membership;
+    try_verify_nullifier_non_membership(nullifier_hash, non_membership, nullifier_root)?;
+
+    let low_leaf = NullifierLeaf {
+        next_value: *nullifier_hash,
+        next_index: proof.leaf_index,
+        ..non_membership.low_leaf
+    };
+    let relinked = merkle_path_root(
+        &low_leaf.hash(),
+        &non_membership.siblings,
+        non_membership.low_leaf_index,
+    )?;
+    if fold_merkle_path([0u8; 32], &proof.siblings, proof.leaf_index)? != relinked {
+        return Err(OrderRejection::NullifierInsertionMismatch);
+    }
+
+    let new_leaf = NullifierLeaf {
+        value: *nullifier_hash,
+        ..non_membership.low_leaf
+    };
+    merkle_path_root(&new_leaf.hash(), &proof.siblings, proof.leaf_index)
+}
+
+/// Rejects any word the active hash would read as a different one
+///
+/// Poseidon reduces its inputs modulo the field order, so without this a note
+/// committed with a balance of 1 could be spent as one of `1 + p`.
+fn ensure_canonical(words: &[[u8; 32]]) -> Result<(), OrderRejection> {
+    if words.iter().all(ActiveHasher::is_canonical) {
+        Ok(())
+    } else {
+        Err(OrderRejection::NonCanonicalValue)
+    }
+}
+
+/// The order's 32-byte integer fields, as they enter its hash
+fn order_words(order: &OrderData) -> [[u8; 32]; 4] {
+    [
+        uint_word(order.amount_in),
+        uint_word(order.min_amount_out),
+        uint_word(order.target_price),
+        uint_word(order.kind.trigger_price()),
+    ]
+}
+
+/// Root reached from an item and its [`TREE_DEPTH`]-long path; shared by the
+/// commitment and spent-nullifier trees
+fn merkle_path_root(
+    item: &[u8; 32],
+    siblings: &[[u8; 32]],
+    leaf_index: u64,
+) -> Result<[u8; 32], OrderRejection> {
+    fold_merkle_path(ActiveHasher::merkle_leaf(item), siblings, leaf_index)
+}
+
+/// Root reached from a tagged leaf node, or the zero node of an empty slot
+fn fold_merkle_path(
+    leaf: [u8; 32],
+    siblings: &[[u8; 32]],
+    leaf_index: u64,
+) -> Result<[u8; 32], OrderRejection> {
+    if siblings.len() != TREE_DEPTH {
+        return Err(OrderRejection::MerklePathLengthMismatch);
+    }
+    if leaf_index >> TREE_DEPTH != 0 {
+        return Err(OrderRejection::LeafIndexOutOfRange);
+    }
+
+    let mut current_hash = leaf;
+
+    // Traverse up the tree
+    for (level, sibling) in siblings.iter().enumerate() {
+        current_hash = if (leaf_index >> level) & 1 == 0 {
             // Current node is left child
-            hasher.update(&current_hash);
-            hasher.update(sibling);
+            ActiveHasher::merkle_node(&current_hash, sibling)
         } else {
             // Current node is right child
-            hasher.update(sibling);
-            hasher.update(&current_hash);
-        }
-        current_hash = hasher.finalize().into();
+            ActiveHasher::merkle_node(sibling, &current_hash)
+        };
     }
 
-    &current_hash == expected_root
+    Ok(current_hash)
 }
 
 /// Legacy balance verification for backward compatibility
+///
+/// Each of `indices` is 0 for a left child or 1 for a right one; any other
+/// value fails the proof rather than passing as a right child.
 pub fn verify_merkle_proof(
     address: &[u8; 20],
-    balance: u64,
-    siblings: &Vec<[u8; 32]>,
-    indices: &Vec<u8>,
+    balance: U256,
+    siblings: &[[u8; 32]],
+    indices: &[u8],
     expected_root: &[u8; 32],
 ) -> bool {
-    if siblings.len() != indices.len() {
+    if siblings.len() != indices.len() || indices.iter().any(|&index| index > 1) {
         return false;
     }
 
@@ -168,7 +718,7 @@ pub fn verify_merkle_proof(
     let mut hasher = Sha256::new();
     hasher.update(b"BALANCE_LEAF"); // Domain separation
     hasher.update(address);
-    hasher.update(&balance.to_le_bytes());
+    hasher.update(uint_word(balance));
     let result = hasher.finalize();
     let mut current_hash = [0u8; 32];
     current_hash.copy_from_slice(&result);
@@ -179,11 +729,11 @@ pub fn verify_merkle_proof(
         hasher.update(b"MERKLE_NODE"); // Domain separation
 
         if indices[i] == 0 {
-            hasher.update(&current_hash);
+            hasher.update(current_hash);
             hasher.update(sibling);
         } else {
             hasher.update(sibling);
-            hasher.update(&current_hash);
+            hasher.update(current_hash);
         }
         current_hash = hasher.finalize().into();
     }
@@ -198,23 +748,179 @@ pub fn verify_nullifier_order(
     commitment_hash: &[u8; 32],
     nullifier_hash: &[u8; 32],
 ) -> bool {
+    try_verify_nullifier_order(commitment, market, commitment_hash, nullifier_hash).is_ok()
+}
+
+/// Like [`verify_nullifier_order`], but reports the first check that failed
+pub fn try_verify_nullifier_order(
+    commitment: &OrderCommitment,
+    market: &MarketConditions,
+    commitment_hash: &[u8; 32],
+    nullifier_hash: &[u8; 32],
+) -> Result<(), OrderRejection> {
     // 1. Verify nullifier knowledge
-    if !verify_nullifier_knowledge(commitment, commitment_hash, nullifier_hash) {
-        return false;
+    try_verify_nullifier_knowledge(commitment, commitment_hash, nullifier_hash)?;
+
+    // 2. Verify the balance is in the token the order sells
+    if commitment.balance_asset != commitment.order_data.token_in {
+        return Err(OrderRejection::BalanceAssetMismatch);
     }
 
-    // 2. Verify sufficient balance
+    // 3. Verify sufficient balance
     if commitment.balance < commitment.order_data.amount_in {
-        return false;
+        return Err(OrderRejection::InsufficientBalance);
     }
 
-    // 3. Verify order conditions
+    // 4. Verify order conditions
     let order_hash = hash_order(&commitment.order_data);
-    if !validate_order(&commitment.order_data, market, &order_hash) {
-        return false;
+    try_validate_order(&commitment.order_data, market, &order_hash)
+}
+
+/// Runs every check the order program makes on one order and returns the
+/// public values it commits
+///
+/// Checks run in a fixed order (nullifier, spent-nullifier tree, commitment
+/// tree, balance asset, fill amount, order conditions) and the first failure is
+/// reported.
+///
+/// The spent note's balance is split: `fill_amount` goes to this fill and the
+/// rest to a change note under `change_nullifier`. The change note carries
+/// `change_order` if the owner gives one, and otherwise [`change_note_order`],
+/// so a good-'til-cancelled order can fill the rest of its amount in later
+/// batches. An order that closes with balance left over needs a change order,
+/// or the rest of the balance could never be spent again.
+pub fn try_verify_order_witness(
+    witness: &OrderWitness,
+    market: &MarketConditions,
+    merkle_root: &[u8; 32],
+    nullifier_root: &[u8; 32],
+) -> Result<OrderPublicValues, OrderRejection> {
+    // 1. Nullifier hash matches the public one (prevents replay)
+    let nullifier_hash = compute_nullifier_hash(&witness.nullifier);
+    if nullifier_hash != witness.nullifier_hash {
+        return Err(OrderRejection::NullifierMismatch);
+    }
+
+    // 2. Nullifier has not been spent
+    try_verify_nullifier_non_membership(&nullifier_hash, &witness.non_membership, nullifier_root)?;
+
+    // 3. Commitment is in the Merkle tree, over values the hash reads exactly
+    ensure_canonical(&order_words(&witness.order))?;
+    ensure_canonical(&[
+        witness.nullifier,
+        uint_word(witness.balance),
+        witness.change_nullifier,
+    ])?;
+    let commitment_hash = compute_commitment_hash(
+        &witness.order,
+        &witness.nullifier,
+        &witness.balance_asset,
+        witness.balance,
+    );
+    try_verify_commitment_merkle_proof(
+        &commitment_hash,
+        &witness.siblings,
+        witness.leaf_index,
+        merkle_root,
+    )?;
+
+    // 4. Note holds the token the order sells
+    let order = &witness.order;
+    if witness.balance_asset != order.token_in {
+        return Err(OrderRejection::BalanceAssetMismatch);
+    }
+
+    // 5. Fill is part of the order and covered by the note
+    if witness.fill_amount.is_zero() || witness.fill_amount > order.amount_in {
+        return Err(OrderRejection::InvalidFillAmount);
+    }
+    if witness.balance < witness.fill_amount {
+        return Err(OrderRejection::InsufficientBalance);
+    }
+    if order.time_in_force == TimeInForce::Fok && witness.fill_amount != order.amount_in {
+        return Err(OrderRejection::FillOrKillPartial);
     }
+    let min_amount_out = min_amount_out_for_fill(order, witness.fill_amount)
+        .ok_or(OrderRejection::InvalidFillAmount)?;
+
+    // 6. Order can execute
+    try_validate_order(order, market, &hash_order(order))?;
+
+    // 7. Change note for what the fill leaves of the balance, in the same asset
+    //    and under an order that can spend it
+    let change = witness.balance - witness.fill_amount;
+    let change_commitment = if change.is_zero() {
+        [0u8; 32]
+    } else {
+        let change_order = match &witness.change_order {
+            Some(change_order) => change_order.clone(),
+            None => change_note_order(order, witness.fill_amount)
+                .ok_or(OrderRejection::ChangeOrderMissing)?,
+        };
+        if change_order.token_in != witness.balance_asset {
+            return Err(OrderRejection::BalanceAssetMismatch);
+        }
+        ensure_canonical(&order_words(&change_order))?;
+        compute_commitment_hash(
+            &change_order,
+            &witness.change_nullifier,
+            &witness.balance_asset,
+            change,
+        )
+    };
 
-    true
+    Ok(OrderPublicValues {
+        valid: true,
+        nullifierHash: nullifier_hash.into(),
+        walletAddress: witness.order.wallet_address.into(),
+        amountIn: witness.fill_amount,
+        minAmountOut: min_amount_out,
+        treeRoot: (*merkle_root).into(),
+        nullifierRoot: (*nullifier_root).into(),
+        changeCommitment: change_commitment.into(),
+        tokenIn: witness.order.token_in.into(),
+        tokenOut: witness.order.token_out.into(),
+        deadline: witness.order.deadline,
+        currentPrice: market.current_price,
+        blockTimestamp: market.block_timestamp,
+    })
+}
+
+/// The order's minimum output scaled to a fill of `fill_amount`, rounded up so a
+/// partial fill never gets a worse rate than the whole order
+///
+/// `None` if the scaled amount overflows.
+pub fn min_amount_out_for_fill(order: &OrderData, fill_amount: U256) -> Option<U256> {
+    if fill_amount == order.amount_in {
+        return Some(order.min_amount_out);
+    }
+    Some(
+        order
+            .min_amount_out
+            .checked_mul(fill_amount)?
+            .div_ceil(order.amount_in),
+    )
+}
+
+/// What is left of `order` once `fill_amount` of it has filled, for its change
+/// note to carry
+///
+/// A good-'til-cancelled order stays open for the rest of its amount, and the
+/// rest of its minimum output once the fill's rounded-up share is taken, so its
+/// fills together never return less than the whole order's minimum.
+///
+/// `None` once the order has closed: an immediate-or-cancel or fill-or-kill
+/// order after its one fill, or any order filled in full. The owner then gives
+/// the change note a fresh order.
+pub fn change_note_order(order: &OrderData, fill_amount: U256) -> Option<OrderData> {
+    if order.time_in_force != TimeInForce::Gtc || fill_amount >= order.amount_in {
+        return None;
+    }
+    Some(OrderData {
+        amount_in: order.amount_in - fill_amount,
+        min_amount_out: order.min_amount_out - min_amount_out_for_fill(order, fill_amount)?,
+        ..order.clone()
+    })
 }
 
 /// Generates a deterministic nullifier from user secret and order context
@@ -222,18 +928,21 @@ pub fn generate_order_nullifier(
     user_secret: &[u8; 32],
     order_context: &[u8; 32], // Could be order hash or trading session ID
 ) -> [u8; 32] {
-    let mut hasher = Sha256::new();
-    hasher.update(b"ORDER_NULLIFIER"); // Domain separation
-    hasher.update(user_secret);
-    hasher.update(order_context);
-    hasher.finalize().into()
+    ActiveHasher::order_nullifier(user_secret, order_context)
+}
+
+/// Default change nullifier for a spent note, derived from its nullifier secret and
+/// commitment so the owner can recompute it and spend the change later
+pub fn derive_change_nullifier(nullifier: &[u8; 32], commitment_hash: &[u8; 32]) -> [u8; 32] {
+    generate_order_nullifier(nullifier, commitment_hash)
 }
 
 /// Creates order commitment for Merkle tree inclusion
 pub fn create_order_commitment(
     order: &OrderData,
     user_secret: &[u8; 32],
-    balance: u64,
+    balance_asset: &[u8; 20],
+    balance: U256,
     order_context: &[u8; 32],
 ) -> (OrderCommitment, NullifierData) {
     // Generate nullifier
@@ -243,13 +952,14 @@ pub fn create_order_commitment(
     let commitment = OrderCommitment {
         order_data: order.clone(),
         nullifier,
+        balance_asset: *balance_asset,
         balance,
     };
 
     // Generate public nullifier data
     let nullifier_data = NullifierData {
         nullifier_hash: compute_nullifier_hash(&nullifier),
-        commitment_hash: compute_commitment_hash(order, &nullifier, balance),
+        commitment_hash: compute_commitment_hash(order, &nullifier, balance_asset, balance),
     };
 
     (commitment, nullifier_data)
@@ -0,0 +1,202 @@
+//! Fixed-depth incremental Merkle tree of order commitments.
+//!
+//! Unfilled positions are padded with precomputed zero subtrees, so appending a
+//! leaf and generating a proof both touch one node per level. Commitments are
+//! stored as [`OrderHasher::merkle_leaf`] hashes and nodes are hashed with
+//! [`ActiveHasher`], the same as [`crate::verify_commitment_merkle_proof`].
+//!
+//! The tree remembers the last [`ROOT_HISTORY_SIZE`] roots, so a proof built
+//! against a root stays acceptable while other commitments are appended.
+
+use crate::hash::{ActiveHasher, OrderHasher};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Depth of the commitment tree; [`crate::verify_commitment_merkle_proof`] only
+/// accepts paths of exactly this length
+pub const TREE_DEPTH: usize = 20;
+
+/// Number of recent roots accepted by [`IncrementalMerkleTree::is_known_root`]
+pub const ROOT_HISTORY_SIZE: usize = 30;
+
+/// Errors from updating or querying an [`IncrementalMerkleTree`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MerkleTreeError {
+    /// Every leaf position is already taken
+    TreeFull,
+    /// No leaf has been appended at this index
+    UnknownLeaf(u64),
+    /// The nullifier hash is already in the spent-nullifier tree
+    NullifierSpent,
+}
+
+impl fmt::Display for MerkleTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TreeFull => f.write_str("Merkle tree is full"),
+            Self::UnknownLeaf(index) => write!(f, "no leaf at index {index}"),
+            Self::NullifierSpent => f.write_str("nullifier has already been spent"),
+        }
+    }
+}
+
+impl std::error::Error for MerkleTreeError {}
+
+/// Append-only Merkle tree with a fixed depth and a window of recent roots
+#[derive(Debug, Clone)]
+pub struct IncrementalMerkleTree {
+    depth: usize,
+    /// `zeros[level]` is the root of an empty subtree of height `level`
+    zeros: Vec<[u8; 32]>,
+    /// Non-empty prefix of each level; `nodes[0]` holds the tagged leaf hashes
+    nodes: Vec<Vec<[u8; 32]>>,
+    /// Last [`ROOT_HISTORY_SIZE`] roots, oldest first
+    roots: VecDeque<[u8; 32]>,
+}
+
+impl Default for IncrementalMerkleTree {
+    fn default() -> Self {
+        Self::new(TREE_DEPTH)
+    }
+}
+
+impl IncrementalMerkleTree {
+    pub fn new(depth: usize) -> Self {
+        assert!(depth > 0 && depth < 64, "tree depth must be in 1..64");
+
+        let mut zeros = Vec::with_capacity(depth + 1);
+        zeros.push([0u8; 32]);
+        for level in 0..depth {
+            zeros.push(ActiveHasher::merkle_node(&zeros[level], &zeros[level]));
+        }
+
+        let mut roots = VecDeque::with_capacity(ROOT_HISTORY_SIZE);
+        roots.push_back(zeros[depth]);
+
+        Self {
+            depth,
+            roots,
+            zeros,
+            nodes: vec![Vec::new(); depth + 1],
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Number of leaves appended so far
+    pub fn len(&self) -> u64 {
+        self.nodes[0].len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes[0].is_empty()
+    }
+
+    pub fn capacity(&self) -> u64 {
+        1u64 << self.depth
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        *self
+            .roots
+            .back()
+            .expect("history always holds the current root")
+    }
+
+    /// Recent roots, oldest first, ending with the current root
+    pub fn root_history(&self) -> impl Iterator<Item = &[u8; 32]> {
+        self.roots.iter()
+    }
+
+    /// Whether `root` is the current root or one of the roots it replaced recently
+    pub fn is_known_root(&self, root: &[u8; 32]) -> bool {
+        self.roots.contains(root)
+    }
+
+    /// Tagged leaf hash stored at `index` (not the raw commitment hash)
+    pub fn leaf(&self, index: u64) -> Option<[u8; 32]> {
+        self.nodes[0].get(usize::try_from(index).ok()?).copied()
+    }
+
+    /// Appends a commitment and returns its leaf index
+    pub fn append(&mut self, commitment_hash: [u8; 32]) -> Result<u64, MerkleTreeError> {
+        let index = self.len();
+        if index >= self.capacity() {
+            return Err(MerkleTreeError::TreeFull);
+        }
+
+        self.nodes[0].push([0u8; 32]);
+        let root = self.write_leaf(index as usize, &commitment_hash);
+
+        if self.roots.len() == ROOT_HISTORY_SIZE {
+            self.roots.pop_front();
+        }
+        self.roots.push_back(root);
+        Ok(index)
+    }
+
+    /// Replaces an existing leaf without recording a new root
+    ///
+    /// Only for trees whose leaves change in place (the spent-nullifier tree);
+    /// the caller must follow up with [`Self::append`], which records the root.
+    pub(crate) fn update(&mut self, index: u64, item: &[u8; 32]) -> Result<(), MerkleTreeError> {
+        if index >= self.len() {
+            return Err(MerkleTreeError::UnknownLeaf(index));
+        }
+        self.write_leaf(index as usize, item);
+        Ok(())
+    }
+
+    /// Stores the tagged leaf for `item` at `position` and rehashes its path,
+    /// returning the new root
+    fn write_leaf(&mut self, position: usize, item: &[u8; 32]) -> [u8; 32] {
+        let mut position = position;
+        let mut node = ActiveHasher::merkle_leaf(item);
+        self.nodes[0][position] = node;
+
+        for level in 0..self.depth {
+            node = if position & 1 == 0 {
+                ActiveHasher::merkle_node(&node, &self.node(level, position + 1))
+            } else {
+                ActiveHasher::merkle_node(&self.node(level, position - 1), &node)
+            };
+            position /= 2;
+
+            let parents = &mut self.nodes[level + 1];
+            if position < parents.len() {
+                parents[position] = node;
+            } else {
+                parents.push(node);
+            }
+        }
+
+        node
+    }
+
+    /// Siblings from the leaf up to the root; the leaf index gives their sides
+    pub fn proof(&self, index: u64) -> Result<Vec<[u8; 32]>, MerkleTreeError> {
+        if index >= self.len() {
+            return Err(MerkleTreeError::UnknownLeaf(index));
+        }
+
+        let mut position = index as usize;
+        let siblings = (0..self.depth)
+            .map(|level| {
+                let sibling = self.node(level, position ^ 1);
+                position /= 2;
+                sibling
+            })
+            .collect();
+
+        Ok(siblings)
+    }
+
+    fn node(&self, level: usize, position: usize) -> [u8; 32] {
+        self.nodes[level]
+            .get(position)
+            .copied()
+            .unwrap_or(self.zeros[level])
+    }
+}
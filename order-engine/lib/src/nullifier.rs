@@ -0,0 +1,182 @@
+//! Indexed Merkle tree of spent nullifier hashes.
+//!
+//! Every leaf holds a spent nullifier hash and a link to the next larger one,
+//! so the leaves form a sorted linked list starting from a zero sentinel. A
+//! nullifier hash is unspent exactly when some leaf's value is below it and its
+//! successor is above it (or the leaf is the last one), so non-membership is a
+//! single Merkle path of [`TREE_DEPTH`](crate::TREE_DEPTH) against the public
+//! nullifier root. Settlement only has to store that root, and only the
+//! current one: an older root still lacks the nullifiers spent since.
+//!
+//! Spending a nullifier is proven the same way: an [`NullifierInsertionProof`]
+//! shows the low leaf against the old root, then an empty slot for the new
+//! leaf once the low leaf links to it, which fixes the new root.
+
+use crate::hash::{ActiveHasher, OrderHasher};
+use crate::merkle::{IncrementalMerkleTree, MerkleTreeError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A spent nullifier hash and the next larger one (zero when it is the largest)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NullifierLeaf {
+    pub value: [u8; 32],
+    pub next_value: [u8; 32],
+    pub next_index: u64,
+}
+
+impl NullifierLeaf {
+    pub fn hash(&self) -> [u8; 32] {
+        ActiveHasher::nullifier_leaf(&self.value, &self.next_value, self.next_index)
+    }
+
+    /// Whether `nullifier_hash` falls strictly between this leaf and its successor,
+    /// comparing the hashes as big-endian integers
+    pub fn covers(&self, nullifier_hash: &[u8; 32]) -> bool {
+        self.value < *nullifier_hash
+            && (self.next_value == [0u8; 32] || *nullifier_hash < self.next_value)
+    }
+}
+
+/// Witness that a nullifier hash is not in the spent-nullifier tree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NullifierNonMembershipProof {
+    /// Leaf whose range covers the nullifier hash
+    pub low_leaf: NullifierLeaf,
+    pub low_leaf_index: u64,
+    pub siblings: Vec<[u8; 32]>,
+}
+
+/// Witness that inserting a nullifier hash into the spent-nullifier tree moves
+/// it from one root to the next
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NullifierInsertionProof {
+    /// The hash is unspent under the old root
+    pub non_membership: NullifierNonMembershipProof,
+    /// Empty slot the new leaf goes into
+    pub leaf_index: u64,
+    /// Path of that slot once the low leaf links to it
+    pub siblings: Vec<[u8; 32]>,
+}
+
+/// Spent nullifier hashes, maintained by whoever settles orders
+#[derive(Debug, Clone)]
+pub struct NullifierTree {
+    tree: IncrementalMerkleTree,
+    leaves: Vec<NullifierLeaf>,
+    /// Leaf index of every spent value, ordered by value
+    indices: BTreeMap<[u8; 32], u64>,
+}
+
+impl Default for NullifierTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NullifierTree {
+    /// Empty tree holding only the zero sentinel leaf
+    pub fn new() -> Self {
+        let sentinel = NullifierLeaf {
+            value: [0u8; 32],
+            next_value: [0u8; 32],
+            next_index: 0,
+        };
+        let mut tree = IncrementalMerkleTree::default();
+        tree.append(sentinel.hash())
+            .expect("empty tree has room for the sentinel");
+
+        Self {
+            tree,
+            leaves: vec![sentinel],
+            indices: BTreeMap::from([([0u8; 32], 0)]),
+        }
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.tree.root()
+    }
+
+    pub fn is_spent(&self, nullifier_hash: &[u8; 32]) -> bool {
+        self.indices.contains_key(nullifier_hash)
+    }
+
+    /// Number of spent nullifiers (not counting the sentinel)
+    pub fn len(&self) -> u64 {
+        self.tree.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Marks a nullifier hash as spent and returns its leaf index
+    pub fn insert(&mut self, nullifier_hash: [u8; 32]) -> Result<u64, MerkleTreeError> {
+        Ok(self.insert_with_proof(nullifier_hash)?.leaf_index)
+    }
+
+    /// Like [`Self::insert`], but returns the witness that the insertion leads
+    /// from the old root to the new one
+    pub fn insert_with_proof(
+        &mut self,
+        nullifier_hash: [u8; 32],
+    ) -> Result<NullifierInsertionProof, MerkleTreeError> {
+        let non_membership = self.non_membership_proof(&nullifier_hash)?;
+        if self.tree.len() >= self.tree.capacity() {
+            return Err(MerkleTreeError::TreeFull);
+        }
+
+        let low_index = non_membership.low_leaf_index;
+        let index = self.tree.len();
+        let low_leaf = self.leaves[low_index as usize];
+        let new_leaf = NullifierLeaf {
+            value: nullifier_hash,
+            next_value: low_leaf.next_value,
+            next_index: low_leaf.next_index,
+        };
+        let low_leaf = NullifierLeaf {
+            next_value: nullifier_hash,
+            next_index: index,
+            ..low_leaf
+        };
+
+        self.tree.update(low_index, &low_leaf.hash())?;
+        self.tree.append(new_leaf.hash())?;
+        self.leaves[low_index as usize] = low_leaf;
+        self.leaves.push(new_leaf);
+        self.indices.insert(nullifier_hash, index);
+        Ok(NullifierInsertionProof {
+            non_membership,
+            leaf_index: index,
+            // A leaf's siblings do not depend on the leaf itself
+            siblings: self.tree.proof(index)?,
+        })
+    }
+
+    /// Non-membership witness against the current root
+    pub fn non_membership_proof(
+        &self,
+        nullifier_hash: &[u8; 32],
+    ) -> Result<NullifierNonMembershipProof, MerkleTreeError> {
+        if self.is_spent(nullifier_hash) {
+            return Err(MerkleTreeError::NullifierSpent);
+        }
+
+        let low_leaf_index = self.low_leaf_index(nullifier_hash);
+        Ok(NullifierNonMembershipProof {
+            low_leaf: self.leaves[low_leaf_index as usize],
+            low_leaf_index,
+            siblings: self.tree.proof(low_leaf_index)?,
+        })
+    }
+
+    /// Index of the largest spent value below `nullifier_hash` (the sentinel at worst)
+    fn low_leaf_index(&self, nullifier_hash: &[u8; 32]) -> u64 {
+        let (_, &index) = self
+            .indices
+            .range(..*nullifier_hash)
+            .next_back()
+            .expect("the zero sentinel is below every nonzero hash");
+        index
+    }
+}
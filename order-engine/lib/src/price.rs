@@ -0,0 +1,57 @@
+//! Fixed-point conversion between base and quote amounts at a market price.
+//!
+//! A price is the number of quote-token base units one whole base token costs,
+//! i.e. quote units per `10^base_decimals` base units, and `10^base_decimals`
+//! is the price's scale factor. The demo's `2050000000` is $2050 per ETH: USDC
+//! has 6 decimals, and ETH's 18 give a scale of `10^18`. The quote token's
+//! decimals are already part of the price.
+//!
+//! Every conversion multiplies before it divides, in 512 bits, and rounds the
+//! result down. An expected output is therefore never overstated: an order
+//! whose minimum is met by a rounded-down amount is met by the exact one.
+
+use crate::{OrderData, OrderSide, U256};
+use alloy_primitives::U512;
+
+/// `10^decimals`, the number of base units in one whole token
+///
+/// `None` above 77 decimals, which no longer fits in 256 bits.
+pub fn price_scale(decimals: u8) -> Option<U256> {
+    U256::from(10u64).checked_pow(U256::from(decimals))
+}
+
+/// Quote units `base_amount` is worth at `price` with the given
+/// [`price_scale`], rounded down
+///
+/// `None` if the result does not fit in 256 bits.
+pub fn quote_amount(base_amount: U256, price: U256, scale: U256) -> Option<U256> {
+    narrow(U512::from(base_amount) * U512::from(price) / U512::from(scale))
+}
+
+/// Base units `quote_amount` buys at `price` with the given [`price_scale`],
+/// rounded down
+///
+/// `None` if the result does not fit in 256 bits, which includes a zero price:
+/// the amount is then unbounded.
+pub fn base_amount(quote_amount: U256, price: U256, scale: U256) -> Option<U256> {
+    if price.is_zero() {
+        return None;
+    }
+    narrow(U512::from(quote_amount) * U512::from(scale) / U512::from(price))
+}
+
+/// What the order's whole input is worth in its output token at `price`,
+/// rounded down
+///
+/// A sell order converts base to quote and a buy order quote to base. `None`
+/// if the amount does not fit in 256 bits.
+pub fn expected_amount_out(order: &OrderData, price: U256, scale: U256) -> Option<U256> {
+    match order.side {
+        OrderSide::Sell => quote_amount(order.amount_in, price, scale),
+        OrderSide::Buy => base_amount(order.amount_in, price, scale),
+    }
+}
+
+fn narrow(value: U512) -> Option<U256> {
+    U256::checked_from_limbs_slice(value.as_limbs())
+}
@@ -0,0 +1,225 @@
+//! Validation of a settlement proposed for a set of matched orders.
+//!
+//! The matcher hands `DarkCoWHook.settleBalances` one transfer per order and
+//! any swaps against the pool. The settlement program proves those arrays are
+//! sound rather than trusting the operator that computed them:
+//!
+//! - every order passes the batch checks, so its input comes from a committed,
+//!   unspent note that covers it;
+//! - transfer `i` fills order `i`: it pays the order's wallet in its output
+//!   token, at least the fill's minimum amount out and at a price no worse than
+//!   the target for the order's side;
+//! - swaps are exact-input sales of collected order inputs, all in one
+//!   direction, each with a price limit;
+//! - every token is conserved: what the fills pay in equals what the transfers
+//!   and swaps pay out;
+//! - inserting the orders' nullifiers, in order, takes the spent-nullifier tree
+//!   to the root the settlement commits, so the contract can move to it without
+//!   trusting the operator for it.
+//!
+//! The token swaps buy is the one exception: the pool sets how much of it they
+//! return when the hook settles. Its payouts are bounded instead by what the
+//! fills pay in plus the least the swaps return at their price limits, so the
+//! operator can never pay out more than the settlement brings in. The hook
+//! reverts a swap its limit stops short of selling its whole input.
+
+use crate::{
+    price_scale, try_verify_batch, try_verify_nullifier_insertion, BatchRejection,
+    MarketConditions, NullifierInsertionProof, OrderData, OrderPublicValues, OrderRejection,
+    OrderSide, OrderWitness, Settlement, SettlementPublicValues, SwapBalance, TransferBalance,
+    U256,
+};
+use alloy_primitives::{aliases::U160, keccak256, Address, U512};
+use alloy_sol_types::SolValue;
+use std::collections::BTreeMap;
+
+/// `keccak256(abi.encode(transferBalances, swapBalances))`, as computed on-chain
+///
+/// Always keccak, whichever hash backend is active, so the contract can check
+/// the arrays it settles against the proof.
+pub fn settlement_hash(transfers: &[TransferBalance], swaps: &[SwapBalance]) -> [u8; 32] {
+    keccak256((transfers.to_vec(), swaps.to_vec()).abi_encode_params()).0
+}
+
+/// Validates the matched orders and the settlement that fills them, and returns
+/// the public values the settlement program commits
+///
+/// `insertions[i]` spends order `i`'s nullifier in the tree left by the ones
+/// before it, starting from `nullifier_root`.
+pub fn try_verify_settlement(
+    witnesses: &[OrderWitness],
+    insertions: &[NullifierInsertionProof],
+    settlement: &Settlement,
+    market: &MarketConditions,
+    merkle_root: &[u8; 32],
+    nullifier_root: &[u8; 32],
+) -> Result<SettlementPublicValues, BatchRejection> {
+    let orders = try_verify_batch(witnesses, market, merkle_root, nullifier_root)?;
+
+    if insertions.len() != witnesses.len() {
+        return Err(BatchRejection {
+            index: witnesses.len().min(insertions.len()) as u32,
+            reason: OrderRejection::NullifierInsertionMismatch,
+        });
+    }
+    let mut new_nullifier_root = *nullifier_root;
+    for (index, (witness, insertion)) in witnesses.iter().zip(insertions).enumerate() {
+        new_nullifier_root =
+            try_verify_nullifier_insertion(&witness.nullifier_hash, insertion, &new_nullifier_root)
+                .map_err(|reason| BatchRejection {
+                    index: index as u32,
+                    reason,
+                })?;
+    }
+
+    let transfers = &settlement.transferBalances;
+    if transfers.len() != witnesses.len() {
+        return Err(BatchRejection {
+            index: witnesses.len().min(transfers.len()) as u32,
+            reason: OrderRejection::TransferCountMismatch,
+        });
+    }
+
+    // Per-currency totals paid in by the fills and paid out by the settlement
+    let mut collected = BTreeMap::new();
+    let mut paid = BTreeMap::new();
+
+    for (index, ((witness, fill), transfer)) in
+        witnesses.iter().zip(&orders).zip(transfers).enumerate()
+    {
+        let reject = |reason| BatchRejection {
+            index: index as u32,
+            reason,
+        };
+
+        try_verify_fill(&witness.order, fill, transfer).map_err(reject)?;
+        credit(&mut collected, fill.tokenIn, fill.amountIn)
+            .and_then(|_| credit(&mut paid, transfer.currency, transfer.amount))
+            .ok_or_else(|| reject(OrderRejection::FlowNotConserved))?;
+    }
+
+    // Every swap sells the same pool currency, which some order paid in, and
+    // brings in at least its output at its price limit
+    let mut bought = None;
+    for (index, swap) in settlement.swapBalances.iter().enumerate() {
+        let reject = || BatchRejection {
+            index: index as u32,
+            reason: OrderRejection::InvalidSwap,
+        };
+
+        let (sold, buys) = if swap.zeroForOne {
+            (settlement.currency0, settlement.currency1)
+        } else {
+            (settlement.currency1, settlement.currency0)
+        };
+        if !swap.amountSpecified.is_negative()
+            || *bought.get_or_insert(buys) != buys
+            || !collected.contains_key(&sold)
+        {
+            return Err(reject());
+        }
+        let amount_in = swap.amountSpecified.unsigned_abs();
+        let amount_out = min_swap_output(amount_in, swap.sqrtPriceLimitX96, swap.zeroForOne)
+            .ok_or_else(reject)?;
+        credit(&mut paid, sold, amount_in)
+            .and_then(|_| credit(&mut collected, buys, amount_out))
+            .ok_or_else(reject)?;
+    }
+
+    // Report the first order touching a currency that is not conserved; the
+    // bought currency may keep whatever the swaps return above their minimum
+    let conserved = |currency: &Address| {
+        let collected = collected.get(currency).unwrap_or(&U256::ZERO);
+        let paid = paid.get(currency).unwrap_or(&U256::ZERO);
+        if Some(*currency) == bought {
+            paid <= collected
+        } else {
+            paid == collected
+        }
+    };
+    for (index, (fill, transfer)) in orders.iter().zip(transfers).enumerate() {
+        if !conserved(&fill.tokenIn) || !conserved(&transfer.currency) {
+            return Err(BatchRejection {
+                index: index as u32,
+                reason: OrderRejection::FlowNotConserved,
+            });
+        }
+    }
+
+    Ok(SettlementPublicValues {
+        settlementHash: settlement_hash(transfers, &settlement.swapBalances).into(),
+        currency0: settlement.currency0,
+        currency1: settlement.currency1,
+        newNullifierRoot: new_nullifier_root.into(),
+        orders,
+    })
+}
+
+/// Checks that a transfer pays for a fill on the order's terms
+fn try_verify_fill(
+    order: &OrderData,
+    fill: &OrderPublicValues,
+    transfer: &TransferBalance,
+) -> Result<(), OrderRejection> {
+    if transfer.sender != fill.walletAddress {
+        return Err(OrderRejection::TransferRecipientMismatch);
+    }
+
+    if transfer.currency != fill.tokenOut {
+        return Err(OrderRejection::TransferCurrencyMismatch);
+    }
+
+    if transfer.amount < fill.minAmountOut {
+        return Err(OrderRejection::FillBelowMinimum);
+    }
+
+    // Cross-multiplied in 512 bits so nothing is rounded or overflows: a sell
+    // gets at least the target in quote per base sold, a buy pays at most the
+    // target in quote per base bought (see `crate::price`)
+    let scale = price_scale(order.base_decimals).ok_or(OrderRejection::InvalidDecimals)?;
+    let wide = |value: U256| U512::from(value);
+    let (received, spent) = (wide(transfer.amount), wide(fill.amountIn));
+    let (target, scale) = (wide(order.target_price), wide(scale));
+    let meets_target = match order.side {
+        OrderSide::Sell => received * scale >= spent * target,
+        OrderSide::Buy => spent * scale <= received * target,
+    };
+    if !meets_target {
+        return Err(OrderRejection::FillBelowTargetPrice);
+    }
+
+    Ok(())
+}
+
+/// The least an exact-input swap of `amount_in` returns when it sells all of it
+/// without passing `sqrt_price_limit_x96`, rounded down
+///
+/// The pool's price is `sqrtPriceX96 = sqrt(amount1 / amount0) * 2^96`. Selling
+/// currency0 lowers it to the limit at worst, so each unit returns at least
+/// `(limit / 2^96)^2` of currency1; selling currency1 raises it, so each unit
+/// returns at least `(2^96 / limit)^2` of currency0. `None` for a zero limit,
+/// which sets no bound, or an output too large for 256 bits.
+fn min_swap_output(
+    amount_in: U256,
+    sqrt_price_limit_x96: U160,
+    zero_for_one: bool,
+) -> Option<U256> {
+    if sqrt_price_limit_x96.is_zero() {
+        return None;
+    }
+    // Each step rounds down, and stays within 512 bits
+    let (amount, limit) = (U512::from(amount_in), U512::from(sqrt_price_limit_x96));
+    let amount_out: U512 = if zero_for_one {
+        (((amount * limit) >> 96) * limit) >> 96
+    } else {
+        (((amount << 96) / limit) << 96) / limit
+    };
+    U256::checked_from_limbs_slice(amount_out.as_limbs())
+}
+
+/// Adds `amount` to a currency's running total, failing on overflow
+fn credit(totals: &mut BTreeMap<Address, U256>, currency: Address, amount: U256) -> Option<()> {
+    let total = totals.entry(currency).or_insert(U256::ZERO);
+    *total = total.checked_add(amount)?;
+    Some(())
+}
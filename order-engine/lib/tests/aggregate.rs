@@ -0,0 +1,85 @@
+use alloy_sol_types::SolType;
+use fibonacci_lib::{
+    try_aggregate_orders, vkey_digest, BatchRejection, OrderCheckResult, OrderPublicValues,
+    OrderRejection, U256,
+};
+
+fn order(nullifier: u8) -> OrderPublicValues {
+    OrderPublicValues {
+        valid: true,
+        nullifierHash: [nullifier; 32].into(),
+        walletAddress: [1u8; 20].into(),
+        amountIn: U256::from(5000000000000000000u64),
+        minAmountOut: U256::from(10000000000u64),
+        treeRoot: [0x11; 32].into(),
+        nullifierRoot: [0x22; 32].into(),
+        changeCommitment: [0x33; 32].into(),
+        tokenIn: [0xAu8; 20].into(),
+        tokenOut: [0xBu8; 20].into(),
+        deadline: 1735689600,
+        currentPrice: U256::from(2050000000u64),
+        blockTimestamp: 1735600000,
+    }
+}
+
+fn encoded(nullifiers: &[u8]) -> Vec<Vec<u8>> {
+    nullifiers
+        .iter()
+        .map(|&n| OrderPublicValues::abi_encode(&order(n)))
+        .collect()
+}
+
+#[test]
+fn order_proofs_aggregate_in_order() {
+    let orders = try_aggregate_orders(&encoded(&[1, 2, 3])).unwrap();
+    let nullifiers: Vec<_> = orders.iter().map(|o| o.nullifierHash.0[0]).collect();
+
+    assert_eq!(nullifiers, [1, 2, 3]);
+}
+
+#[test]
+fn only_enforced_order_values_are_accepted() {
+    let check = OrderCheckResult::abi_encode(&OrderCheckResult { reason: 0 });
+    let mut invalid = order(2);
+    invalid.valid = false;
+    let mut padded = OrderPublicValues::abi_encode(&order(2));
+    padded.extend([0u8; 32]);
+
+    for bad in [check, OrderPublicValues::abi_encode(&invalid), padded] {
+        let mut public_values = encoded(&[1]);
+        public_values.push(bad);
+        assert_eq!(
+            try_aggregate_orders(&public_values).err(),
+            Some(BatchRejection {
+                index: 1,
+                reason: OrderRejection::InvalidOrderPublicValues,
+            })
+        );
+    }
+}
+
+#[test]
+fn nullifiers_must_be_distinct() {
+    assert_eq!(
+        try_aggregate_orders(&encoded(&[1, 2, 1])).err(),
+        Some(BatchRejection {
+            index: 2,
+            reason: OrderRejection::DuplicateNullifier,
+        })
+    );
+    assert_eq!(
+        try_aggregate_orders(&[]).err(),
+        Some(BatchRejection {
+            index: 0,
+            reason: OrderRejection::EmptyBatch,
+        })
+    );
+}
+
+#[test]
+fn vkey_digest_is_big_endian_words() {
+    let digest = vkey_digest(&[0x01020304, 0, 0, 0, 0, 0, 0, 0x0a0b0c0d]);
+
+    assert_eq!(digest[..4], [1, 2, 3, 4]);
+    assert_eq!(digest[28..], [0xa, 0xb, 0xc, 0xd]);
+}
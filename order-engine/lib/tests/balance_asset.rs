@@ -0,0 +1,84 @@
+mod common;
+
+use common::{market, order, spend_last, Note, ETH, USDC, WETH};
+use fibonacci_lib::{
+    compute_nullifier_hash, create_order_commitment, try_verify_nullifier_order,
+    try_verify_order_witness, OrderRejection, U256,
+};
+
+/// Spends a 10 ETH note committed with `balance_asset` against the order, through
+/// the full witness checks
+fn spend(balance_asset: [u8; 20]) -> Result<(), OrderRejection> {
+    let order = order();
+    let note = Note {
+        balance_asset,
+        ..Note::new(order.clone(), [1u8; 32], U256::from(10 * ETH))
+    };
+    let (mut witness, root, nullifier_root) = spend_last(&[note], order.amount_in);
+    // The owner places the same order again with the rest of the note
+    witness.change_order = Some(order);
+    try_verify_order_witness(&witness, &market(), &root, &nullifier_root).map(|_| ())
+}
+
+#[test]
+fn note_is_spendable_in_its_own_token() {
+    assert_eq!(spend(WETH), Ok(()));
+}
+
+#[test]
+fn note_in_another_token_is_rejected() {
+    // A committed USDC balance cannot fund an order selling ETH
+    assert_eq!(spend(USDC), Err(OrderRejection::BalanceAssetMismatch));
+}
+
+#[test]
+fn relabelled_asset_breaks_the_commitment() {
+    let order = order();
+    let balance = U256::from(10 * ETH);
+    let (mut commitment, data) =
+        create_order_commitment(&order, &[1u8; 32], &USDC, balance, &[0u8; 32]);
+
+    assert_eq!(
+        try_verify_nullifier_order(
+            &commitment,
+            &market(),
+            &data.commitment_hash,
+            &data.nullifier_hash
+        ),
+        Err(OrderRejection::BalanceAssetMismatch)
+    );
+
+    // Claiming the note is WETH after committing it as USDC changes its hash
+    commitment.balance_asset = WETH;
+    assert_eq!(
+        try_verify_nullifier_order(
+            &commitment,
+            &market(),
+            &data.commitment_hash,
+            &compute_nullifier_hash(&commitment.nullifier)
+        ),
+        Err(OrderRejection::CommitmentMismatch)
+    );
+}
+
+/// Under Poseidon, a balance above the field modulus hashes like a small one
+#[cfg(feature = "poseidon")]
+#[test]
+fn balance_aliased_above_the_field_modulus_is_rejected() {
+    let modulus: U256 =
+        "21888242871839275222246405745257275088548364400416034343698204186575808495617"
+            .parse()
+            .unwrap();
+    let order = order();
+    let (mut witness, root, nullifier_root) = spend_last(
+        &[Note::new(order.clone(), [1u8; 32], U256::from(1u64))],
+        order.amount_in,
+    );
+
+    // Claims the 1-wei note holds 1 + p, enough for any fill
+    witness.balance = U256::from(1u64) + modulus;
+    assert_eq!(
+        try_verify_order_witness(&witness, &market(), &root, &nullifier_root).map(|_| ()),
+        Err(OrderRejection::NonCanonicalValue)
+    );
+}
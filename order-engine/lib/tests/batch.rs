@@ -0,0 +1,75 @@
+mod common;
+
+use common::{market, order, spend_all, Note, ETH};
+use fibonacci_lib::{
+    try_verify_batch, BatchRejection, OrderData, OrderRejection, OrderWitness, U256,
+};
+
+/// Witnesses for one 10 ETH note per secret, each spent against an order from
+/// wallet `[secret; 20]`, all in one tree; returns them with the commitment and
+/// nullifier roots
+fn batch(secrets: &[u8]) -> (Vec<OrderWitness>, [u8; 32], [u8; 32]) {
+    let notes: Vec<_> = secrets
+        .iter()
+        .map(|&secret| {
+            let order = OrderData {
+                wallet_address: [secret; 20],
+                ..order()
+            };
+            Note::new(order, [secret; 32], U256::from(10 * ETH))
+        })
+        .collect();
+    let (mut witnesses, root, nullifier_root) = spend_all(&notes);
+    for witness in &mut witnesses {
+        // The owner places the same order again with the rest of the note
+        witness.change_order = Some(witness.order.clone());
+    }
+    (witnesses, root, nullifier_root)
+}
+
+#[test]
+fn valid_batch_commits_every_order() {
+    let (witnesses, root, nullifier_root) = batch(&[1, 2, 3]);
+    let orders = try_verify_batch(&witnesses, &market(), &root, &nullifier_root).unwrap();
+
+    assert_eq!(orders.len(), 3);
+    for (order, witness) in orders.iter().zip(&witnesses) {
+        assert_eq!(order.nullifierHash, witness.nullifier_hash);
+        assert_eq!(order.treeRoot, root);
+    }
+}
+
+#[test]
+fn repeated_nullifier_is_rejected() {
+    let (mut witnesses, root, nullifier_root) = batch(&[1, 2]);
+    witnesses.push(witnesses[0].clone());
+
+    assert_eq!(
+        try_verify_batch(&witnesses, &market(), &root, &nullifier_root).err(),
+        Some(BatchRejection {
+            index: 2,
+            reason: OrderRejection::DuplicateNullifier,
+        })
+    );
+}
+
+#[test]
+fn first_invalid_order_is_reported() {
+    let (mut witnesses, root, nullifier_root) = batch(&[1, 2, 3]);
+    witnesses[1].balance = U256::ZERO;
+
+    assert_eq!(
+        try_verify_batch(&witnesses, &market(), &root, &nullifier_root).err(),
+        Some(BatchRejection {
+            index: 1,
+            reason: OrderRejection::MerkleRootMismatch,
+        })
+    );
+    assert_eq!(
+        try_verify_batch(&[], &market(), &root, &nullifier_root).err(),
+        Some(BatchRejection {
+            index: 0,
+            reason: OrderRejection::EmptyBatch,
+        })
+    );
+}
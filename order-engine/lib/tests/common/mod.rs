@@ -0,0 +1,149 @@
+//! Fixtures shared by the integration tests
+//!
+//! Every test crate compiles its own copy and uses only part of it.
+#![allow(dead_code)]
+
+use fibonacci_lib::{
+    compute_commitment_hash, compute_nullifier_hash, IncrementalMerkleTree, MarketConditions,
+    NullifierInsertionProof, NullifierTree, OrderData, OrderKind, OrderSide, OrderWitness,
+    TimeInForce, U256,
+};
+
+pub const ETH: u64 = 1000000000000000000;
+pub const WETH: [u8; 20] = [0xA; 20];
+pub const USDC: [u8; 20] = [0xB; 20];
+
+/// `amount` dollars in USDC units (6 decimals)
+pub fn usd(amount: u64) -> U256 {
+    U256::from(amount * 1000000)
+}
+
+/// The market at `current_price`, at the time every test runs
+pub fn market_at(current_price: U256) -> MarketConditions {
+    MarketConditions {
+        current_price,
+        block_timestamp: 1735600000,
+    }
+}
+
+/// ETH at $2050
+pub fn market() -> MarketConditions {
+    market_at(usd(2050))
+}
+
+/// Sells 5 ETH for at least 10k USDC at $2000 or better
+pub fn order() -> OrderData {
+    OrderData {
+        wallet_address: [1u8; 20],
+        token_in: WETH,
+        token_out: USDC,
+        amount_in: U256::from(5 * ETH),
+        min_amount_out: usd(10000),
+        target_price: usd(2000),
+        deadline: 1735689600,
+        side: OrderSide::Sell,
+        base_decimals: 18,
+        kind: OrderKind::Limit,
+        time_in_force: TimeInForce::Gtc,
+        valid_after: 0,
+    }
+}
+
+/// Buys at least 3.8 ETH with 8k USDC at $2100 or better
+pub fn buy_order() -> OrderData {
+    OrderData {
+        token_in: USDC,
+        token_out: WETH,
+        amount_in: usd(8000),
+        min_amount_out: U256::from(3800000000000000000u64),
+        target_price: usd(2100),
+        side: OrderSide::Buy,
+        ..order()
+    }
+}
+
+/// A note holding `balance` of `balance_asset` under `nullifier`, committed to `order`
+pub struct Note {
+    pub order: OrderData,
+    pub nullifier: [u8; 32],
+    pub balance_asset: [u8; 20],
+    pub balance: U256,
+}
+
+impl Note {
+    /// A note holding `balance` of its order's input
+    pub fn new(order: OrderData, nullifier: [u8; 32], balance: U256) -> Self {
+        Self {
+            balance_asset: order.token_in,
+            order,
+            nullifier,
+            balance,
+        }
+    }
+
+    pub fn commitment_hash(&self) -> [u8; 32] {
+        compute_commitment_hash(
+            &self.order,
+            &self.nullifier,
+            &self.balance_asset,
+            self.balance,
+        )
+    }
+}
+
+/// Appends the notes to a fresh tree and returns a witness spending each one,
+/// with the commitment and nullifier roots
+///
+/// Each witness fills its order in full, has no change order, and gives the
+/// change note the nullifier `[nullifier[0] + 1; 32]`.
+pub fn spend_all(notes: &[Note]) -> (Vec<OrderWitness>, [u8; 32], [u8; 32]) {
+    let mut tree = IncrementalMerkleTree::default();
+    let leaf_indices: Vec<_> = notes
+        .iter()
+        .map(|note| tree.append(note.commitment_hash()).unwrap())
+        .collect();
+    let spent = NullifierTree::new();
+
+    let witnesses = notes
+        .iter()
+        .zip(leaf_indices)
+        .map(|(note, leaf_index)| {
+            let nullifier_hash = compute_nullifier_hash(&note.nullifier);
+            OrderWitness {
+                nullifier_hash,
+                order: note.order.clone(),
+                nullifier: note.nullifier,
+                balance_asset: note.balance_asset,
+                balance: note.balance,
+                siblings: tree.proof(leaf_index).unwrap(),
+                leaf_index,
+                non_membership: spent.non_membership_proof(&nullifier_hash).unwrap(),
+                fill_amount: note.order.amount_in,
+                change_nullifier: [note.nullifier[0] + 1; 32],
+                change_order: None,
+            }
+        })
+        .collect();
+
+    (witnesses, tree.root(), spent.root())
+}
+
+/// Like [`spend_all`], but returns only the witness spending the last note,
+/// filling `fill_amount` of its order
+pub fn spend_last(notes: &[Note], fill_amount: U256) -> (OrderWitness, [u8; 32], [u8; 32]) {
+    let (mut witnesses, root, nullifier_root) = spend_all(notes);
+    let mut witness = witnesses.pop().unwrap();
+    witness.fill_amount = fill_amount;
+    (witness, root, nullifier_root)
+}
+
+/// Proofs inserting each witness's nullifier in turn into the empty
+/// spent-nullifier tree [`spend_all`] proves against, with the root after them
+pub fn insert_all(witnesses: &[OrderWitness]) -> (Vec<NullifierInsertionProof>, [u8; 32]) {
+    let mut spent = NullifierTree::new();
+    let insertions = witnesses
+        .iter()
+        .map(|witness| spent.insert_with_proof(witness.nullifier_hash).unwrap())
+        .collect();
+    (insertions, spent.root())
+}
@@ -0,0 +1,223 @@
+//! Fixed test vectors for every hash backend.
+//!
+//! A change to any of these values breaks every existing commitment, nullifier
+//! and tree root, so they must only ever be updated deliberately.
+
+use alloy_primitives::{hex, keccak256};
+use fibonacci_lib::{
+    KeccakHasher, OrderData, OrderHasher, OrderKind, OrderSide, PoseidonHasher, Sha256Hasher,
+    TimeInForce, U256,
+};
+
+const NULLIFIER: [u8; 32] = [3u8; 32];
+const LEFT: [u8; 32] = [1u8; 32];
+const RIGHT: [u8; 32] = [2u8; 32];
+
+fn order() -> OrderData {
+    OrderData {
+        wallet_address: [1u8; 20],
+        token_in: [0xAu8; 20],
+        token_out: [0xBu8; 20],
+        amount_in: U256::from(5000000000000000000u64),
+        min_amount_out: U256::from(10000000000u64),
+        target_price: U256::from(2000000000u64),
+        deadline: 1735689600,
+        side: OrderSide::Sell,
+        base_decimals: 18,
+        kind: OrderKind::StopLoss {
+            trigger_price: U256::from(1900000000u64),
+        },
+        time_in_force: TimeInForce::Ioc,
+        valid_after: 1735600000,
+    }
+}
+
+fn balance() -> U256 {
+    U256::from(10000000000000000000u64)
+}
+
+#[test]
+fn sha256_vectors() {
+    let order_hash = Sha256Hasher::hash_order(&order());
+    assert_eq!(
+        order_hash,
+        hex!("f8ceea2e09157dea112c88b37cd5f77b96227030c81632da415537767eca27ca")
+    );
+    assert_eq!(
+        Sha256Hasher::nullifier_hash(&NULLIFIER),
+        hex!("e03fd1b45e3cd927e0244ac805fc10923a455ebbe2c6cf2215148d998754ba38")
+    );
+    assert_eq!(
+        Sha256Hasher::commitment_hash(&order_hash, &NULLIFIER, &order().token_in, balance()),
+        hex!("4e3d887a2cf2bc2c19bf59c2548fd3ce97d8430afd3179774af45327ca3abefd")
+    );
+    assert_eq!(
+        Sha256Hasher::nullifier_leaf(&LEFT, &RIGHT, 7),
+        hex!("5e6b74fd71cd385ea0f42acd6a2b9c378d40ec6146d7c383afa1381ded7208ff")
+    );
+    assert_eq!(
+        Sha256Hasher::merkle_leaf(&LEFT),
+        hex!("24416342e09c8520610268ebc38fba82d431b96c4ba0ebc864cfd132ae391216")
+    );
+    assert_eq!(
+        Sha256Hasher::merkle_node(&LEFT, &RIGHT),
+        hex!("434821c1d2fe5f5bcae8561452195d9e3b379ee8fe34721359f37d7cc389748f")
+    );
+    assert_eq!(
+        Sha256Hasher::order_nullifier(&LEFT, &RIGHT),
+        hex!("ec453b6e937e0a128e5ed26b86f4c7d344a0842678f84020fd5d3e05093fe6e1")
+    );
+}
+
+#[test]
+fn keccak_vectors() {
+    let order_hash = KeccakHasher::hash_order(&order());
+    assert_eq!(
+        order_hash,
+        hex!("6155e59694fceb83a19eedfebb356a0f086aaf34c1bb28b0357fe6a4bc03974e")
+    );
+    assert_eq!(
+        KeccakHasher::nullifier_hash(&NULLIFIER),
+        hex!("4d23092654a8e8e8ff1c9456c2a9ed523a3ee04db23ced4ab90e4cb39681399e")
+    );
+    assert_eq!(
+        KeccakHasher::commitment_hash(&order_hash, &NULLIFIER, &order().token_in, balance()),
+        hex!("1e2ab697d20c84a6a6f14741f117f62b62a98245f56176f206f63016cece7932")
+    );
+    assert_eq!(
+        KeccakHasher::nullifier_leaf(&LEFT, &RIGHT, 7),
+        hex!("17690595bf4bb4964401da23739b15885e9d8d610c54cce88bb04830c8834463")
+    );
+    assert_eq!(
+        KeccakHasher::merkle_leaf(&LEFT),
+        hex!("af5071d2c3d227cd4fe1a7a3d56d1913e6a2594d2c77ace4fb523fcddb61d753")
+    );
+    assert_eq!(
+        KeccakHasher::merkle_node(&LEFT, &RIGHT),
+        hex!("6683143979f771d4a1d65f7bc8b5124dffe1ac1aee950e87a9e7f281e7e89c96")
+    );
+    assert_eq!(
+        KeccakHasher::order_nullifier(&LEFT, &RIGHT),
+        hex!("042cf1331a5ce8a066590ee430f57a33f0beda9a5fd8dfca355449cbd176717a")
+    );
+}
+
+#[test]
+fn poseidon_vectors() {
+    let order_hash = PoseidonHasher::hash_order(&order());
+    assert_eq!(
+        order_hash,
+        hex!("0d67dccf10f828c959543df6724221f487a69b119719a01134b7d6af5d24ce49")
+    );
+    assert_eq!(
+        PoseidonHasher::nullifier_hash(&NULLIFIER),
+        hex!("112e2bd5a1714a3bfebe17dc8572e19db6cd13d1aeeac4a90d34dc670414f7b2")
+    );
+    assert_eq!(
+        PoseidonHasher::commitment_hash(&order_hash, &NULLIFIER, &order().token_in, balance()),
+        hex!("06fe9c42ccee3a190c2481c7ff77d5daa6e28aedbc6de2d23cf6e4bcf415c7b3")
+    );
+    assert_eq!(
+        PoseidonHasher::nullifier_leaf(&LEFT, &RIGHT, 7),
+        hex!("0b15210e35c2b3bf9b1b14f6d8d5b64c078a28008fb8cd5467d4a08c41394cca")
+    );
+    assert_eq!(
+        PoseidonHasher::merkle_leaf(&LEFT),
+        hex!("05350bccc96295cd116a7471aa9cb63557c3e8dbcf8a8fa6e69ae389f027059f")
+    );
+    assert_eq!(
+        PoseidonHasher::merkle_node(&LEFT, &RIGHT),
+        hex!("1cd880bc43a17c9679f135f3267b1ce495d4c5708dae21a509040b074565166f")
+    );
+    assert_eq!(
+        PoseidonHasher::order_nullifier(&LEFT, &RIGHT),
+        hex!("1014eb1bcf5da6c3466f87c38241492a68141fb65ced65aa174acceff4dbaf80")
+    );
+}
+
+/// Builds the `abi.encode` words by hand to pin the layout Solidity has to reproduce
+#[test]
+fn keccak_matches_abi_encoding() {
+    let tag = |name: &[u8]| {
+        let mut tag = [0u8; 32];
+        tag[..name.len()].copy_from_slice(name);
+        tag
+    };
+    let encoded = [tag(b"MERKLE_NODE"), LEFT, RIGHT].concat();
+    assert_eq!(
+        KeccakHasher::merkle_node(&LEFT, &RIGHT),
+        keccak256(encoded).0
+    );
+    let encoded = [tag(b"MERKLE_LEAF"), LEFT].concat();
+    assert_eq!(KeccakHasher::merkle_leaf(&LEFT), keccak256(encoded).0);
+
+    let word = |value: U256| value.to_be_bytes::<32>();
+    let address = |addr: [u8; 20]| {
+        let mut word = [0u8; 32];
+        word[12..].copy_from_slice(&addr);
+        word
+    };
+    let o = order();
+    let encoded = [
+        address(o.wallet_address),
+        address(o.token_in),
+        address(o.token_out),
+        word(o.amount_in),
+        word(o.min_amount_out),
+        word(o.target_price),
+        word(U256::from(o.deadline)),
+        word(U256::from(o.side as u8)),
+        word(U256::from(o.base_decimals)),
+        word(U256::from(1u64)), // OrderKind::StopLoss
+        word(U256::from(1900000000u64)),
+        word(U256::from(1u64)), // TimeInForce::Ioc
+        word(U256::from(o.valid_after)),
+    ]
+    .concat();
+    assert_eq!(KeccakHasher::hash_order(&o), keccak256(encoded).0);
+
+    let order_hash = KeccakHasher::hash_order(&o);
+    let encoded = [
+        tag(b"COMMITMENT_HASH"),
+        order_hash,
+        NULLIFIER,
+        address(o.token_in),
+        word(balance()),
+    ]
+    .concat();
+    assert_eq!(
+        KeccakHasher::commitment_hash(&order_hash, &NULLIFIER, &o.token_in, balance()),
+        keccak256(encoded).0
+    );
+}
+
+#[test]
+fn poseidon_words_must_be_below_the_modulus() {
+    let modulus: U256 =
+        "21888242871839275222246405745257275088548364400416034343698204186575808495617"
+            .parse()
+            .unwrap();
+    let word = |value: U256| value.to_be_bytes::<32>();
+
+    // One past a small balance and one past it plus the modulus collide...
+    let order_hash = PoseidonHasher::hash_order(&order());
+    let aliased = U256::from(1u64) + modulus;
+    assert_eq!(
+        PoseidonHasher::commitment_hash(
+            &order_hash,
+            &NULLIFIER,
+            &order().token_in,
+            U256::from(1u64)
+        ),
+        PoseidonHasher::commitment_hash(&order_hash, &NULLIFIER, &order().token_in, aliased)
+    );
+
+    // ...so only words below the modulus are accepted
+    assert!(PoseidonHasher::is_canonical(&word(
+        modulus - U256::from(1u64)
+    )));
+    assert!(!PoseidonHasher::is_canonical(&word(modulus)));
+    assert!(!PoseidonHasher::is_canonical(&word(aliased)));
+    assert!(Sha256Hasher::is_canonical(&word(aliased)));
+    assert!(KeccakHasher::is_canonical(&word(aliased)));
+}
@@ -0,0 +1,239 @@
+use fibonacci_lib::{
+    try_verify_commitment_merkle_proof, verify_commitment_merkle_proof, verify_merkle_proof,
+    ActiveHasher, IncrementalMerkleTree, MerkleTreeError, OrderHasher, OrderRejection,
+    ROOT_HISTORY_SIZE, TREE_DEPTH, U256,
+};
+use sha2::{Digest, Sha256};
+
+fn leaf(i: u8) -> [u8; 32] {
+    [i + 1; 32]
+}
+
+/// Root of a full tree of the given depth, padding missing leaves with zeros
+fn naive_root(leaves: &[[u8; 32]], depth: usize) -> [u8; 32] {
+    let mut level: Vec<_> = leaves.iter().map(ActiveHasher::merkle_leaf).collect();
+    level.resize(1 << depth, [0u8; 32]);
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| ActiveHasher::merkle_node(&pair[0], &pair[1]))
+            .collect();
+    }
+    level[0]
+}
+
+#[test]
+fn root_matches_full_recomputation() {
+    let mut tree = IncrementalMerkleTree::new(4);
+    assert_eq!(tree.root(), naive_root(&[], 4));
+
+    let mut leaves = Vec::new();
+    for i in 0..7 {
+        leaves.push(leaf(i));
+        assert_eq!(tree.append(leaf(i)), Ok(i as u64));
+        assert_eq!(tree.root(), naive_root(&leaves, 4));
+    }
+    assert_eq!(tree.root_history().count(), 8);
+}
+
+#[test]
+fn proofs_verify_against_current_root() {
+    let mut tree = IncrementalMerkleTree::default();
+    for i in 0..5 {
+        tree.append(leaf(i)).unwrap();
+    }
+
+    for i in 0..5 {
+        let siblings = tree.proof(i).unwrap();
+        assert_eq!(siblings.len(), TREE_DEPTH);
+        assert!(verify_commitment_merkle_proof(
+            &leaf(i as u8),
+            &siblings,
+            i,
+            &tree.root()
+        ));
+    }
+    assert_eq!(tree.proof(5), Err(MerkleTreeError::UnknownLeaf(5)));
+}
+
+#[test]
+fn paths_must_match_tree_depth() {
+    let mut tree = IncrementalMerkleTree::default();
+    tree.append(leaf(0)).unwrap();
+    let root = tree.root();
+    let siblings = tree.proof(0).unwrap();
+
+    // A commitment cannot pose as its own root with an empty path
+    assert_eq!(
+        try_verify_commitment_merkle_proof(&leaf(0), &[], 0, &leaf(0)),
+        Err(OrderRejection::MerklePathLengthMismatch)
+    );
+    assert_eq!(
+        try_verify_commitment_merkle_proof(&leaf(0), &siblings[1..], 0, &root),
+        Err(OrderRejection::MerklePathLengthMismatch)
+    );
+
+    let mut longer = siblings.clone();
+    longer.push([0u8; 32]);
+    assert_eq!(
+        try_verify_commitment_merkle_proof(&leaf(0), &longer, 0, &root),
+        Err(OrderRejection::MerklePathLengthMismatch)
+    );
+
+    // A shorter tree's root is rejected even though its path is self-consistent
+    let mut shallow = IncrementalMerkleTree::new(4);
+    shallow.append(leaf(0)).unwrap();
+    assert!(!verify_commitment_merkle_proof(
+        &leaf(0),
+        &shallow.proof(0).unwrap(),
+        0,
+        &shallow.root()
+    ));
+}
+
+#[test]
+fn append_fails_once_full() {
+    let mut tree = IncrementalMerkleTree::new(2);
+    for i in 0..4 {
+        tree.append(leaf(i)).unwrap();
+    }
+    assert_eq!(tree.append(leaf(4)), Err(MerkleTreeError::TreeFull));
+}
+
+#[test]
+fn root_window_drops_oldest_roots() {
+    let mut tree = IncrementalMerkleTree::new(8);
+    let empty_root = tree.root();
+    let mut roots = vec![empty_root];
+    for i in 0..ROOT_HISTORY_SIZE as u8 {
+        tree.append(leaf(i)).unwrap();
+        roots.push(tree.root());
+    }
+
+    // One append past the window evicts exactly the empty-tree root
+    assert!(!tree.is_known_root(&empty_root));
+    assert!(roots[1..].iter().all(|root| tree.is_known_root(root)));
+    assert_eq!(tree.root_history().count(), ROOT_HISTORY_SIZE);
+    assert_eq!(tree.root_history().last(), Some(&tree.root()));
+    assert!(!tree.is_known_root(&[0xffu8; 32]));
+}
+
+/// Root reached by the old verifier: untagged leaves, any path length, and
+/// every nonzero index byte treated as "right child"
+fn legacy_root(leaf: &[u8; 32], siblings: &[[u8; 32]], indices: &[u8]) -> [u8; 32] {
+    siblings
+        .iter()
+        .zip(indices)
+        .fold(*leaf, |node, (sibling, &index)| {
+            if index == 0 {
+                ActiveHasher::merkle_node(&node, sibling)
+            } else {
+                ActiveHasher::merkle_node(sibling, &node)
+            }
+        })
+}
+
+fn path_bits(leaf_index: u64) -> Vec<u8> {
+    (0..TREE_DEPTH)
+        .map(|i| ((leaf_index >> i) & 1) as u8)
+        .collect()
+}
+
+#[test]
+fn internal_node_cannot_pose_as_commitment() {
+    let mut tree = IncrementalMerkleTree::default();
+    tree.append(leaf(0)).unwrap();
+    tree.append(leaf(1)).unwrap();
+    let root = tree.root();
+    let siblings = tree.proof(0).unwrap();
+
+    // Parent of leaves 0 and 1, with the rest of leaf 0's path above it
+    let internal = ActiveHasher::merkle_node(
+        &ActiveHasher::merkle_leaf(&leaf(0)),
+        &ActiveHasher::merkle_leaf(&leaf(1)),
+    );
+    let upper = &siblings[1..];
+    assert_eq!(legacy_root(&internal, upper, &path_bits(0)[1..]), root);
+
+    assert!(!verify_commitment_merkle_proof(&internal, upper, 0, &root));
+
+    // Padding the path back to full depth does not help either: the node is
+    // re-hashed as a leaf, so the walk starts from a different value
+    let mut padded = vec![ActiveHasher::merkle_leaf(&leaf(1))];
+    padded.extend_from_slice(upper);
+    assert_eq!(
+        try_verify_commitment_merkle_proof(&internal, &padded, 0, &root),
+        Err(OrderRejection::MerkleRootMismatch)
+    );
+}
+
+#[test]
+fn leaf_index_bits_beyond_depth_are_rejected() {
+    let mut tree = IncrementalMerkleTree::default();
+    for i in 0..4 {
+        tree.append(leaf(i)).unwrap();
+    }
+    let root = tree.root();
+    let siblings = tree.proof(3).unwrap();
+    assert!(verify_commitment_merkle_proof(
+        &leaf(3),
+        &siblings,
+        3,
+        &root
+    ));
+
+    // The old verifier read index 7 as "right child", so a malformed path still passed
+    let mut malformed = path_bits(3);
+    malformed[0] = 7;
+    let leaf_hash = ActiveHasher::merkle_leaf(&leaf(3));
+    assert_eq!(legacy_root(&leaf_hash, &siblings, &malformed), root);
+
+    // The packed equivalent sets bits the path never reads
+    for leaf_index in [3 | (1 << TREE_DEPTH), 3 | (1 << 63), u64::MAX] {
+        assert_eq!(
+            try_verify_commitment_merkle_proof(&leaf(3), &siblings, leaf_index, &root),
+            Err(OrderRejection::LeafIndexOutOfRange)
+        );
+    }
+}
+
+#[test]
+fn tree_stores_tagged_leaves() {
+    let mut tree = IncrementalMerkleTree::default();
+    let index = tree.append(leaf(0)).unwrap();
+    assert_eq!(tree.leaf(index), Some(ActiveHasher::merkle_leaf(&leaf(0))));
+    assert_ne!(tree.leaf(index), Some(leaf(0)));
+}
+
+#[test]
+fn legacy_path_indices_must_be_bits() {
+    let (address, balance, sibling) = ([1u8; 20], U256::from(7u64), [2u8; 32]);
+    let leaf: [u8; 32] = Sha256::new()
+        .chain_update(b"BALANCE_LEAF")
+        .chain_update(address)
+        .chain_update(balance.to_be_bytes::<32>())
+        .finalize()
+        .into();
+    let root: [u8; 32] = Sha256::new()
+        .chain_update(b"MERKLE_NODE")
+        .chain_update(sibling)
+        .chain_update(leaf)
+        .finalize()
+        .into();
+
+    assert!(verify_merkle_proof(
+        &address,
+        balance,
+        &[sibling],
+        &[1],
+        &root
+    ));
+    // 2 used to pass as a right child
+    assert!(!verify_merkle_proof(
+        &address,
+        balance,
+        &[sibling],
+        &[2],
+        &root
+    ));
+}
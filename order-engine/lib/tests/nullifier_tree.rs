@@ -0,0 +1,148 @@
+use fibonacci_lib::{
+    try_verify_nullifier_insertion, try_verify_nullifier_non_membership,
+    verify_nullifier_non_membership, MerkleTreeError, NullifierLeaf, NullifierTree, OrderRejection,
+};
+
+/// A hash below every hash backend's field modulus, ordered by `byte`
+fn hash(byte: u8) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    hash[1] = byte;
+    hash
+}
+
+#[test]
+fn unspent_nullifiers_have_witnesses() {
+    let mut tree = NullifierTree::new();
+    for byte in [0x40, 0x10, 0x80] {
+        tree.insert(hash(byte)).unwrap();
+    }
+    assert_eq!(tree.len(), 3);
+
+    // Below, between and above the spent values
+    for byte in [0x01, 0x20, 0x50, 0xff] {
+        let proof = tree.non_membership_proof(&hash(byte)).unwrap();
+        assert!(verify_nullifier_non_membership(
+            &hash(byte),
+            &proof,
+            &tree.root()
+        ));
+    }
+}
+
+#[test]
+fn spent_nullifiers_are_rejected() {
+    let mut tree = NullifierTree::new();
+    tree.insert(hash(0x40)).unwrap();
+
+    assert!(tree.is_spent(&hash(0x40)));
+    assert_eq!(
+        tree.insert(hash(0x40)),
+        Err(MerkleTreeError::NullifierSpent)
+    );
+    assert_eq!(
+        tree.non_membership_proof(&hash(0x40)),
+        Err(MerkleTreeError::NullifierSpent)
+    );
+    assert_eq!(
+        tree.non_membership_proof(&[0u8; 32]),
+        Err(MerkleTreeError::NullifierSpent)
+    );
+}
+
+#[test]
+fn witness_goes_stale_once_spent() {
+    let mut tree = NullifierTree::new();
+    tree.insert(hash(0x10)).unwrap();
+    let proof = tree.non_membership_proof(&hash(0x40)).unwrap();
+    let old_root = tree.root();
+
+    tree.insert(hash(0x40)).unwrap();
+    assert_ne!(tree.root(), old_root);
+
+    // The old witness only proves absence from the old root
+    assert_eq!(
+        try_verify_nullifier_non_membership(&hash(0x40), &proof, &tree.root()),
+        Err(OrderRejection::NullifierRootMismatch)
+    );
+
+    // The low leaf now points at the spent value, so no witness covers it
+    let low_leaf = tree.non_membership_proof(&hash(0x20)).unwrap().low_leaf;
+    assert_eq!(low_leaf.next_value, hash(0x40));
+    assert!(!low_leaf.covers(&hash(0x40)));
+}
+
+#[test]
+fn forged_low_leaf_is_rejected() {
+    let mut tree = NullifierTree::new();
+    tree.insert(hash(0x40)).unwrap();
+
+    // Claim the sentinel's successor is past the spent value
+    let mut proof = tree.non_membership_proof(&hash(0x20)).unwrap();
+    proof.low_leaf = NullifierLeaf {
+        next_value: hash(0x80),
+        ..proof.low_leaf
+    };
+    assert_eq!(
+        try_verify_nullifier_non_membership(&hash(0x40), &proof, &tree.root()),
+        Err(OrderRejection::NullifierRootMismatch)
+    );
+}
+
+/// Under Poseidon, a successor above the field modulus hashes like the spent value
+#[cfg(feature = "poseidon")]
+#[test]
+fn aliased_successor_cannot_cover_a_spent_value() {
+    use fibonacci_lib::U256;
+
+    let modulus: U256 =
+        "21888242871839275222246405745257275088548364400416034343698204186575808495617"
+            .parse()
+            .unwrap();
+    let mut tree = NullifierTree::new();
+    tree.insert(hash(0x40)).unwrap();
+
+    // The sentinel's leaf hash is unchanged, but its range now reaches past 0x40
+    let mut proof = tree.non_membership_proof(&hash(0x20)).unwrap();
+    proof.low_leaf.next_value = (U256::from_be_bytes(hash(0x40)) + modulus).to_be_bytes();
+    assert_eq!(
+        try_verify_nullifier_non_membership(&hash(0x40), &proof, &tree.root()),
+        Err(OrderRejection::NonCanonicalValue)
+    );
+}
+
+#[test]
+fn insertion_proofs_lead_to_the_new_root() {
+    let mut tree = NullifierTree::new();
+    for byte in [0x40, 0x10, 0x80, 0x20] {
+        let root = tree.root();
+        let proof = tree.insert_with_proof(hash(byte)).unwrap();
+        assert_eq!(
+            try_verify_nullifier_insertion(&hash(byte), &proof, &root),
+            Ok(tree.root())
+        );
+    }
+}
+
+#[test]
+fn insertion_must_take_an_empty_slot() {
+    let mut tree = NullifierTree::new();
+    tree.insert(hash(0x40)).unwrap();
+    let root = tree.root();
+    let proof = tree.insert_with_proof(hash(0x10)).unwrap();
+
+    // Over the low leaf itself, or over a leaf already spent
+    for leaf_index in [0, 1] {
+        let mut forged = proof.clone();
+        forged.leaf_index = leaf_index;
+        assert_eq!(
+            try_verify_nullifier_insertion(&hash(0x10), &forged, &root),
+            Err(OrderRejection::NullifierInsertionMismatch)
+        );
+    }
+
+    // Already spent, so no low leaf covers it
+    assert_eq!(
+        try_verify_nullifier_insertion(&hash(0x40), &proof, &root),
+        Err(OrderRejection::NullifierSpent)
+    );
+}
@@ -0,0 +1,205 @@
+mod common;
+
+use common::{buy_order, market, market_at, spend_last, usd, Note, ETH};
+use fibonacci_lib::{
+    change_note_order, compute_commitment_hash, hash_order, try_validate_order,
+    try_verify_order_witness, MarketConditions, OrderData, OrderKind, OrderRejection, OrderSide,
+    TimeInForce, U256,
+};
+
+/// Sells 5 ETH at $1800 or better, or buys ETH with 8k USDC at $2200 or better
+fn order(side: OrderSide, kind: OrderKind) -> OrderData {
+    let order = match side {
+        OrderSide::Sell => OrderData {
+            min_amount_out: usd(9000),
+            target_price: usd(1800),
+            ..common::order()
+        },
+        OrderSide::Buy => OrderData {
+            min_amount_out: U256::from(3 * ETH),
+            target_price: usd(2200),
+            ..buy_order()
+        },
+    };
+    OrderData { kind, ..order }
+}
+
+fn check(order: &OrderData, price: u64) -> Result<(), OrderRejection> {
+    try_validate_order(order, &market_at(usd(price)), &hash_order(order))
+}
+
+fn stop_loss(price: u64) -> OrderKind {
+    OrderKind::StopLoss {
+        trigger_price: usd(price),
+    }
+}
+
+fn take_profit(price: u64) -> OrderKind {
+    OrderKind::TakeProfit {
+        trigger_price: usd(price),
+    }
+}
+
+/// Spends a note of 10 ETH against a sell order, filling `fill_amount` of it
+fn spend(
+    order: &OrderData,
+    fill_amount: U256,
+    change_order: Option<OrderData>,
+) -> Result<[u8; 32], OrderRejection> {
+    spend_note(
+        order,
+        [1u8; 32],
+        U256::from(10 * ETH),
+        fill_amount,
+        change_order,
+    )
+}
+
+/// Spends a note of `balance` under `nullifier` against `order` and returns its
+/// change commitment; the change note's nullifier is `[nullifier[0] + 1; 32]`
+fn spend_note(
+    order: &OrderData,
+    nullifier: [u8; 32],
+    balance: U256,
+    fill_amount: U256,
+    change_order: Option<OrderData>,
+) -> Result<[u8; 32], OrderRejection> {
+    let note = Note::new(order.clone(), nullifier, balance);
+    let (mut witness, root, nullifier_root) = spend_last(&[note], fill_amount);
+    witness.change_order = change_order;
+    try_verify_order_witness(&witness, &market(), &root, &nullifier_root)
+        .map(|values| values.changeCommitment.0)
+}
+
+#[test]
+fn stop_loss_waits_for_the_price_to_move_against_the_order() {
+    // Sell once ETH falls to $1900, but never below $1800
+    let sell = order(OrderSide::Sell, stop_loss(1900));
+    assert_eq!(check(&sell, 2050), Err(OrderRejection::TriggerNotReached));
+    assert_eq!(check(&sell, 1900), Ok(()));
+    assert_eq!(check(&sell, 1799), Err(OrderRejection::PriceBelowTarget));
+
+    // Buy once ETH rises to $2100, but never above $2200
+    let buy = order(OrderSide::Buy, stop_loss(2100));
+    assert_eq!(check(&buy, 2050), Err(OrderRejection::TriggerNotReached));
+    assert_eq!(check(&buy, 2100), Ok(()));
+    assert_eq!(check(&buy, 2201), Err(OrderRejection::PriceAboveTarget));
+}
+
+#[test]
+fn take_profit_waits_for_the_price_to_move_in_the_orders_favour() {
+    let sell = order(OrderSide::Sell, take_profit(2100));
+    assert_eq!(check(&sell, 2050), Err(OrderRejection::TriggerNotReached));
+    assert_eq!(check(&sell, 2100), Ok(()));
+
+    let buy = order(OrderSide::Buy, take_profit(1900));
+    assert_eq!(check(&buy, 2050), Err(OrderRejection::TriggerNotReached));
+    assert_eq!(check(&buy, 1900), Ok(()));
+}
+
+#[test]
+fn order_cannot_execute_before_its_start_time() {
+    let mut sell = order(OrderSide::Sell, OrderKind::Limit);
+    sell.valid_after = 1735600000;
+    let hash = hash_order(&sell);
+    assert_eq!(
+        try_validate_order(
+            &sell,
+            &MarketConditions {
+                block_timestamp: 1735599999,
+                ..market()
+            },
+            &hash
+        ),
+        Err(OrderRejection::OrderNotYetValid)
+    );
+    assert_eq!(try_validate_order(&sell, &market(), &hash), Ok(()));
+}
+
+#[test]
+fn kind_and_time_in_force_are_bound_into_the_order_hash() {
+    let limit = order(OrderSide::Sell, OrderKind::Limit);
+    let stop = order(OrderSide::Sell, stop_loss(1900));
+    let mut fok = limit.clone();
+    fok.time_in_force = TimeInForce::Fok;
+
+    assert_ne!(hash_order(&stop), hash_order(&limit));
+    assert_ne!(
+        hash_order(&stop),
+        hash_order(&order(OrderSide::Sell, take_profit(1900)))
+    );
+    assert_ne!(
+        hash_order(&stop),
+        hash_order(&order(OrderSide::Sell, stop_loss(1901)))
+    );
+    assert_ne!(hash_order(&fok), hash_order(&limit));
+
+    // Dropping the trigger from a stop order does not pass as a limit order
+    assert_eq!(
+        try_validate_order(&limit, &market(), &hash_order(&stop)),
+        Err(OrderRejection::OrderHashMismatch)
+    );
+}
+
+#[test]
+fn fill_or_kill_must_fill_in_full() {
+    let mut sell = order(OrderSide::Sell, OrderKind::Limit);
+    sell.time_in_force = TimeInForce::Fok;
+    assert_eq!(
+        spend(&sell, U256::from(2 * ETH), None),
+        Err(OrderRejection::FillOrKillPartial)
+    );
+    assert!(spend(&sell, U256::from(5 * ETH), Some(sell.clone())).is_ok());
+}
+
+#[test]
+fn immediate_or_cancel_remainder_goes_to_a_fresh_order() {
+    let mut sell = order(OrderSide::Sell, OrderKind::Limit);
+    sell.time_in_force = TimeInForce::Ioc;
+
+    // The order closes after its one fill, so the other 8 ETH need an order from the owner
+    assert!(change_note_order(&sell, U256::from(2 * ETH)).is_none());
+    assert_eq!(
+        spend(&sell, U256::from(2 * ETH), None),
+        Err(OrderRejection::ChangeOrderMissing)
+    );
+    let mut in_usdc = order(OrderSide::Sell, OrderKind::Limit);
+    in_usdc.token_in = sell.token_out;
+    assert_eq!(
+        spend(&sell, U256::from(2 * ETH), Some(in_usdc)),
+        Err(OrderRejection::BalanceAssetMismatch)
+    );
+
+    // Offering the 8 ETH until filled, from $1900
+    let mut next = order(OrderSide::Sell, OrderKind::Limit);
+    next.amount_in = U256::from(8 * ETH);
+    next.min_amount_out = usd(15200);
+    next.target_price = usd(1900);
+    let change_commitment = spend(&sell, U256::from(2 * ETH), Some(next.clone())).unwrap();
+    assert_eq!(
+        change_commitment,
+        compute_commitment_hash(&next, &[2u8; 32], &sell.token_in, U256::from(8 * ETH))
+    );
+
+    // The change note is spendable against the fresh order
+    assert_eq!(
+        spend_note(
+            &next,
+            [2u8; 32],
+            U256::from(8 * ETH),
+            U256::from(8 * ETH),
+            None
+        ),
+        Ok([0u8; 32])
+    );
+
+    // A good-'til-cancelled order stays open in its change note for the other 3 ETH
+    sell.time_in_force = TimeInForce::Gtc;
+    let change_commitment = spend(&sell, U256::from(2 * ETH), None).unwrap();
+    let open = change_note_order(&sell, U256::from(2 * ETH)).unwrap();
+    assert_eq!(open.amount_in, U256::from(3 * ETH));
+    assert_eq!(
+        change_commitment,
+        compute_commitment_hash(&open, &[2u8; 32], &sell.token_in, U256::from(8 * ETH))
+    );
+}
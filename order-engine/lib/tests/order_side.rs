@@ -0,0 +1,47 @@
+mod common;
+
+use common::{buy_order, market_at, usd};
+use fibonacci_lib::{hash_order, try_validate_order, OrderData, OrderRejection, OrderSide};
+
+/// Alice sells 5 ETH for USDC at $2000 or better
+fn alice() -> OrderData {
+    common::order()
+}
+
+/// Bob buys ETH with 8k USDC at $2100 or better
+fn bob() -> OrderData {
+    OrderData {
+        wallet_address: [2u8; 20],
+        ..buy_order()
+    }
+}
+
+fn check(order: &OrderData, price: u64) -> Result<(), OrderRejection> {
+    try_validate_order(order, &market_at(usd(price)), &hash_order(order))
+}
+
+#[test]
+fn sell_order_needs_the_price_at_or_above_target() {
+    assert_eq!(check(&alice(), 2050), Ok(()));
+    assert_eq!(check(&alice(), 2000), Ok(()));
+    assert_eq!(check(&alice(), 1999), Err(OrderRejection::PriceBelowTarget));
+}
+
+#[test]
+fn buy_order_needs_the_price_at_or_below_target() {
+    assert_eq!(check(&bob(), 2050), Ok(()));
+    assert_eq!(check(&bob(), 2100), Ok(()));
+    assert_eq!(check(&bob(), 2101), Err(OrderRejection::PriceAboveTarget));
+}
+
+#[test]
+fn side_is_bound_into_the_order_hash() {
+    // Flipping Bob's side would let his cap be used as a floor
+    let mut flipped = bob();
+    flipped.side = OrderSide::Sell;
+    assert_ne!(hash_order(&flipped), hash_order(&bob()));
+    assert_eq!(
+        try_validate_order(&flipped, &market_at(usd(2050)), &hash_order(&bob())),
+        Err(OrderRejection::PriceBelowTarget)
+    );
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use common::{market, order, spend_last, Note, ETH};
+use fibonacci_lib::{
+    change_note_order, min_amount_out_for_fill, try_verify_order_witness, OrderRejection, U256,
+};
+
+/// A note holding `balance` of ETH under `nullifier`, for the 5 ETH order
+fn note(nullifier: [u8; 32], balance: u64) -> Note {
+    Note::new(order(), nullifier, U256::from(balance))
+}
+
+fn rejection(notes: &[Note], fill_amount: U256) -> Option<OrderRejection> {
+    let (witness, root, nullifier_root) = spend_last(notes, fill_amount);
+    try_verify_order_witness(&witness, &market(), &root, &nullifier_root).err()
+}
+
+#[test]
+fn change_note_can_be_spent_in_a_later_batch() {
+    let first = || note([1u8; 32], 10 * ETH);
+    let (witness, root, nullifier_root) = spend_last(&[first()], U256::from(2 * ETH));
+    let fill = try_verify_order_witness(&witness, &market(), &root, &nullifier_root).unwrap();
+
+    assert_eq!(fill.amountIn, U256::from(2 * ETH));
+    assert_eq!(fill.minAmountOut, U256::from(4000000000u64));
+
+    // The change holds the other 8 ETH under the fresh nullifier, and the order
+    // has 3 ETH left to sell for the other 6k USDC
+    let rest = change_note_order(&order(), U256::from(2 * ETH)).unwrap();
+    assert_eq!(rest.amount_in, U256::from(3 * ETH));
+    assert_eq!(rest.min_amount_out, U256::from(6000000000u64));
+    let change = Note::new(rest, witness.change_nullifier, U256::from(8 * ETH));
+    assert_eq!(fill.changeCommitment, change.commitment_hash());
+
+    // The change cannot fill the original 5 ETH again
+    let notes = [first(), change];
+    let (witness, root, nullifier_root) = spend_last(&notes, U256::from(5 * ETH));
+    assert_eq!(
+        try_verify_order_witness(&witness, &market(), &root, &nullifier_root).err(),
+        Some(OrderRejection::InvalidFillAmount)
+    );
+
+    // Filling what is left completes the order at its full minimum, and the
+    // other 5 ETH need a fresh order from the owner
+    let (mut witness, root, nullifier_root) = spend_last(&notes, U256::from(3 * ETH));
+    assert_eq!(
+        try_verify_order_witness(&witness, &market(), &root, &nullifier_root).err(),
+        Some(OrderRejection::ChangeOrderMissing)
+    );
+    witness.change_order = Some(order());
+    let fill = try_verify_order_witness(&witness, &market(), &root, &nullifier_root).unwrap();
+    assert_eq!(fill.amountIn, U256::from(3 * ETH));
+    assert_eq!(fill.minAmountOut, U256::from(6000000000u64));
+}
+
+#[test]
+fn spending_the_whole_balance_leaves_no_change() {
+    let (witness, root, nullifier_root) =
+        spend_last(&[note([1u8; 32], 5 * ETH)], U256::from(5 * ETH));
+    let fill = try_verify_order_witness(&witness, &market(), &root, &nullifier_root).unwrap();
+
+    assert_eq!(fill.changeCommitment, [0u8; 32]);
+}
+
+#[test]
+fn fill_must_fit_the_order_and_the_note() {
+    let holding = |balance| note([1u8; 32], balance);
+
+    assert_eq!(
+        rejection(&[holding(10 * ETH)], U256::ZERO),
+        Some(OrderRejection::InvalidFillAmount)
+    );
+    assert_eq!(
+        rejection(&[holding(10 * ETH)], U256::from(5 * ETH + 1)),
+        Some(OrderRejection::InvalidFillAmount)
+    );
+    assert_eq!(
+        rejection(&[holding(3 * ETH)], U256::from(4 * ETH)),
+        Some(OrderRejection::InsufficientBalance)
+    );
+    assert_eq!(rejection(&[holding(3 * ETH)], U256::from(3 * ETH)), None);
+}
+
+#[test]
+fn partial_minimum_rounds_up() {
+    let mut order = order();
+    order.amount_in = U256::from(3u64);
+    order.min_amount_out = U256::from(10u64);
+
+    assert_eq!(
+        min_amount_out_for_fill(&order, U256::from(1u64)),
+        Some(U256::from(4u64))
+    );
+    assert_eq!(
+        min_amount_out_for_fill(&order, U256::from(3u64)),
+        Some(U256::from(10u64))
+    );
+    order.min_amount_out = U256::MAX;
+    assert_eq!(min_amount_out_for_fill(&order, U256::from(2u64)), None);
+}
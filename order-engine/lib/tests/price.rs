@@ -0,0 +1,122 @@
+mod common;
+
+use common::{buy_order, market_at, usd, ETH};
+use fibonacci_lib::{
+    base_amount, expected_amount_out, hash_order, price_scale, quote_amount, try_validate_order,
+    OrderData, OrderRejection, OrderSide, U256,
+};
+
+const USDC: u64 = 1000000;
+
+fn eth_scale() -> U256 {
+    price_scale(18).unwrap()
+}
+
+fn order(side: OrderSide, amount_in: U256, min_amount_out: U256) -> OrderData {
+    let order = match side {
+        OrderSide::Sell => common::order(),
+        OrderSide::Buy => buy_order(),
+    };
+    OrderData {
+        amount_in,
+        min_amount_out,
+        ..order
+    }
+}
+
+fn check(order: &OrderData, price: U256) -> Result<(), OrderRejection> {
+    try_validate_order(order, &market_at(price), &hash_order(order))
+}
+
+#[test]
+fn scale_is_ten_to_the_decimals() {
+    assert_eq!(price_scale(0), Some(U256::from(1u64)));
+    assert_eq!(price_scale(6), Some(U256::from(USDC)));
+    assert_eq!(price_scale(18), Some(U256::from(ETH)));
+    assert!(price_scale(77).is_some());
+    assert_eq!(price_scale(78), None);
+}
+
+#[test]
+fn conversions_round_down() {
+    // 5 ETH at $2050 is exactly $10250
+    assert_eq!(
+        quote_amount(U256::from(5 * ETH), usd(2050), eth_scale()),
+        Some(usd(10250))
+    );
+    // One wei is worth less than one USDC unit
+    assert_eq!(
+        quote_amount(U256::from(1u64), usd(2050), eth_scale()),
+        Some(U256::ZERO)
+    );
+    // $8000 at $2050 is 3.902439024390243902439... ETH
+    assert_eq!(
+        base_amount(usd(8000), usd(2050), eth_scale()),
+        Some(U256::from(3902439024390243902u64))
+    );
+}
+
+#[test]
+fn unbounded_conversions_are_none() {
+    assert_eq!(base_amount(usd(8000), U256::ZERO, eth_scale()), None);
+    assert_eq!(quote_amount(U256::MAX, U256::MAX, U256::from(1u64)), None);
+    assert_eq!(base_amount(U256::MAX, U256::from(1u64), eth_scale()), None);
+}
+
+#[test]
+fn expected_output_follows_the_side() {
+    let sell = order(OrderSide::Sell, U256::from(5 * ETH), usd(10000));
+    assert_eq!(
+        expected_amount_out(&sell, usd(2050), eth_scale()),
+        Some(usd(10250))
+    );
+
+    let buy = order(
+        OrderSide::Buy,
+        usd(8000),
+        U256::from(3800000000000000000u64),
+    );
+    assert_eq!(
+        expected_amount_out(&buy, usd(2050), eth_scale()),
+        Some(U256::from(3902439024390243902u64))
+    );
+}
+
+#[test]
+fn minimum_must_be_reachable_at_the_market_price() {
+    // Selling 5 ETH at $2050 yields $10250: a minimum of exactly that passes,
+    // one unit more cannot fill
+    let sell = |min| order(OrderSide::Sell, U256::from(5 * ETH), min);
+    assert_eq!(check(&sell(usd(10250)), usd(2050)), Ok(()));
+    assert_eq!(
+        check(&sell(usd(10250) + U256::from(1u64)), usd(2050)),
+        Err(OrderRejection::MinAmountOutUnreachable)
+    );
+
+    // Buying with $8000 at $2050 yields the rounded-down ETH amount
+    let buy = |min| order(OrderSide::Buy, usd(8000), min);
+    let expected = U256::from(3902439024390243902u64);
+    assert_eq!(check(&buy(expected), usd(2050)), Ok(()));
+    assert_eq!(
+        check(&buy(expected + U256::from(1u64)), usd(2050)),
+        Err(OrderRejection::MinAmountOutUnreachable)
+    );
+}
+
+#[test]
+fn decimals_set_the_scale() {
+    // Read with 12 base decimals, the same 5 * 10^18 units are a million times more
+    let mut sell = order(OrderSide::Sell, U256::from(5 * ETH), usd(10000));
+    sell.base_decimals = 12;
+    assert_eq!(
+        expected_amount_out(&sell, usd(2050), price_scale(12).unwrap()),
+        Some(usd(10250) * U256::from(USDC))
+    );
+    assert_eq!(check(&sell, usd(2050)), Ok(()));
+
+    sell.base_decimals = 78;
+    assert_eq!(
+        check(&sell, usd(2050)),
+        Err(OrderRejection::InvalidDecimals)
+    );
+}
@@ -0,0 +1,415 @@
+mod common;
+
+use alloy_primitives::{aliases::U160, keccak256, Address, I256};
+use alloy_sol_types::SolValue;
+use common::{insert_all, market_at, spend_all, Note};
+use fibonacci_lib::{
+    settlement_hash, try_verify_settlement, BatchRejection, MarketConditions, NullifierTree,
+    OrderData, OrderRejection, OrderSide, OrderWitness, Settlement, SwapBalance, TransferBalance,
+    U256,
+};
+
+const TOKEN_A: [u8; 20] = [0xA; 20];
+const TOKEN_B: [u8; 20] = [0xB; 20];
+const TOKEN_C: [u8; 20] = [0xC; 20];
+
+fn ether(amount: u64) -> U256 {
+    U256::from(amount) * U256::from(10u64).pow(U256::from(18))
+}
+
+/// One market price is checked against every order's target, so keep it above all of them
+fn market() -> MarketConditions {
+    market_at(ether(1000))
+}
+
+/// An order from wallet `[wallet; 20]` selling `amount_in` for at least
+/// `min_amount_out`, priced at exactly that ratio
+fn order(
+    wallet: u8,
+    token_in: [u8; 20],
+    token_out: [u8; 20],
+    amount_in: u64,
+    min_out: u64,
+) -> OrderData {
+    OrderData {
+        wallet_address: [wallet; 20],
+        token_in,
+        token_out,
+        amount_in: ether(amount_in),
+        min_amount_out: ether(min_out),
+        target_price: ether(min_out) / U256::from(amount_in),
+        ..common::order()
+    }
+}
+
+/// Witnesses spending a note of exactly each order's input, all in one tree,
+/// with the commitment and nullifier roots
+fn witnesses(orders: &[OrderData]) -> (Vec<OrderWitness>, [u8; 32], [u8; 32]) {
+    let notes: Vec<_> = orders
+        .iter()
+        .map(|order| {
+            Note::new(
+                order.clone(),
+                [order.wallet_address[0]; 32],
+                order.amount_in,
+            )
+        })
+        .collect();
+    spend_all(&notes)
+}
+
+fn transfer(amount: u64, currency: [u8; 20], wallet: u8) -> TransferBalance {
+    TransferBalance {
+        amount: ether(amount),
+        currency: currency.into(),
+        sender: Address::from([wallet; 20]),
+    }
+}
+
+/// A → B → C → A: each order is filled with the next order's input
+fn cycle() -> (Vec<OrderWitness>, Settlement, [u8; 32], [u8; 32]) {
+    let (witnesses, root, nullifier_root) = witnesses(&[
+        order(1, TOKEN_A, TOKEN_B, 5, 10),
+        order(2, TOKEN_B, TOKEN_C, 10, 20),
+        order(3, TOKEN_C, TOKEN_A, 20, 5),
+    ]);
+    let settlement = Settlement {
+        currency0: TOKEN_A.into(),
+        currency1: TOKEN_B.into(),
+        transferBalances: vec![
+            transfer(10, TOKEN_B, 1),
+            transfer(20, TOKEN_C, 2),
+            transfer(5, TOKEN_A, 3),
+        ],
+        swapBalances: vec![],
+    };
+    (witnesses, settlement, root, nullifier_root)
+}
+
+fn rejection(
+    witnesses: &[OrderWitness],
+    settlement: &Settlement,
+    root: [u8; 32],
+    nullifier_root: [u8; 32],
+) -> Option<BatchRejection> {
+    let (insertions, _) = insert_all(witnesses);
+    try_verify_settlement(
+        witnesses,
+        &insertions,
+        settlement,
+        &market(),
+        &root,
+        &nullifier_root,
+    )
+    .err()
+}
+
+#[test]
+fn balanced_cycle_settles() {
+    let (witnesses, settlement, root, nullifier_root) = cycle();
+    let (insertions, new_nullifier_root) = insert_all(&witnesses);
+    let public_values = try_verify_settlement(
+        &witnesses,
+        &insertions,
+        &settlement,
+        &market(),
+        &root,
+        &nullifier_root,
+    )
+    .unwrap();
+
+    assert_eq!(public_values.orders.len(), 3);
+    assert_eq!(public_values.newNullifierRoot, new_nullifier_root);
+    assert_eq!(
+        public_values.settlementHash,
+        settlement_hash(&settlement.transferBalances, &settlement.swapBalances)
+    );
+}
+
+#[test]
+fn nullifiers_are_inserted_in_order() {
+    let (witnesses, settlement, root, nullifier_root) = cycle();
+    let (insertions, _) = insert_all(&witnesses);
+    let settle = |insertions: &[_]| {
+        try_verify_settlement(
+            &witnesses,
+            insertions,
+            &settlement,
+            &market(),
+            &root,
+            &nullifier_root,
+        )
+        .err()
+    };
+
+    assert_eq!(
+        settle(&insertions[..2]),
+        Some(BatchRejection {
+            index: 2,
+            reason: OrderRejection::NullifierInsertionMismatch,
+        })
+    );
+
+    // The second insertion must be made against the root the first one left,
+    // not the one the settlement starts from
+    let mut stale = insertions.clone();
+    stale[1] = NullifierTree::new()
+        .insert_with_proof(witnesses[1].nullifier_hash)
+        .unwrap();
+    assert_eq!(
+        settle(&stale),
+        Some(BatchRejection {
+            index: 1,
+            reason: OrderRejection::NullifierRootMismatch,
+        })
+    );
+}
+
+#[test]
+fn settlement_hash_matches_abi_encoding() {
+    let (_, settlement, _, _) = cycle();
+    let encoded = (
+        settlement.transferBalances.clone(),
+        settlement.swapBalances.clone(),
+    )
+        .abi_encode_params();
+
+    assert_eq!(
+        settlement_hash(&settlement.transferBalances, &settlement.swapBalances),
+        keccak256(encoded).0
+    );
+}
+
+#[test]
+fn fills_must_meet_the_order_terms() {
+    let (witnesses, settlement, root, nullifier_root) = cycle();
+
+    let mut wrong_wallet = settlement.clone();
+    wrong_wallet.transferBalances[1].sender = Address::from([9u8; 20]);
+    assert_eq!(
+        rejection(&witnesses, &wrong_wallet, root, nullifier_root),
+        Some(BatchRejection {
+            index: 1,
+            reason: OrderRejection::TransferRecipientMismatch,
+        })
+    );
+
+    let mut wrong_token = settlement.clone();
+    wrong_token.transferBalances[0].currency = TOKEN_C.into();
+    assert_eq!(
+        rejection(&witnesses, &wrong_token, root, nullifier_root),
+        Some(BatchRejection {
+            index: 0,
+            reason: OrderRejection::TransferCurrencyMismatch,
+        })
+    );
+
+    let mut short = settlement.clone();
+    short.transferBalances[2].amount -= U256::from(1u64);
+    assert_eq!(
+        rejection(&witnesses, &short, root, nullifier_root),
+        Some(BatchRejection {
+            index: 2,
+            reason: OrderRejection::FillBelowMinimum,
+        })
+    );
+
+    let mut missing = settlement;
+    missing.transferBalances.pop();
+    assert_eq!(
+        rejection(&witnesses, &missing, root, nullifier_root),
+        Some(BatchRejection {
+            index: 2,
+            reason: OrderRejection::TransferCountMismatch,
+        })
+    );
+}
+
+#[test]
+fn fills_must_meet_the_target_price() {
+    // Minimum out is met, but 10 B for 5 A is below a target of 3 B per A
+    let mut rich = order(1, TOKEN_A, TOKEN_B, 5, 10);
+    rich.target_price = ether(3);
+    let (witnesses, root, nullifier_root) = witnesses(&[
+        rich,
+        order(2, TOKEN_B, TOKEN_C, 10, 20),
+        order(3, TOKEN_C, TOKEN_A, 20, 5),
+    ]);
+    let (_, settlement, _, _) = cycle();
+
+    assert_eq!(
+        rejection(&witnesses, &settlement, root, nullifier_root),
+        Some(BatchRejection {
+            index: 0,
+            reason: OrderRejection::FillBelowTargetPrice,
+        })
+    );
+}
+
+#[test]
+fn buy_fills_must_not_exceed_the_target_price() {
+    // Wallet 1 sells 5 A for at least 9 B and gets 10; wallet 2 buys them
+    // paying at most 2 B per A
+    let mut buy = order(2, TOKEN_B, TOKEN_A, 10, 5);
+    buy.side = OrderSide::Buy;
+    buy.target_price = ether(2);
+    let orders = [order(1, TOKEN_A, TOKEN_B, 5, 9), buy];
+    let settlement = Settlement {
+        currency0: TOKEN_A.into(),
+        currency1: TOKEN_B.into(),
+        transferBalances: vec![transfer(10, TOKEN_B, 1), transfer(5, TOKEN_A, 2)],
+        swapBalances: vec![],
+    };
+    // Both sides clear at a market price of 2 B per A
+    let market = MarketConditions {
+        current_price: ether(2),
+        ..market()
+    };
+
+    let (matched, root, nullifier_root) = witnesses(&orders);
+    let (insertions, _) = insert_all(&matched);
+    assert!(try_verify_settlement(
+        &matched,
+        &insertions,
+        &settlement,
+        &market,
+        &root,
+        &nullifier_root
+    )
+    .is_ok());
+
+    // With both targets and the market at 1.9 B per A, paying 10 B for 5 A is
+    // above the buyer's cap
+    let price = ether(19) / U256::from(10u64);
+    let mut capped = orders;
+    capped[0].target_price = price;
+    capped[1].target_price = price;
+    let market = MarketConditions {
+        current_price: price,
+        ..market
+    };
+    let (matched, root, nullifier_root) = witnesses(&capped);
+    let (insertions, _) = insert_all(&matched);
+    assert_eq!(
+        try_verify_settlement(
+            &matched,
+            &insertions,
+            &settlement,
+            &market,
+            &root,
+            &nullifier_root
+        )
+        .err(),
+        Some(BatchRejection {
+            index: 1,
+            reason: OrderRejection::FillBelowTargetPrice,
+        })
+    );
+}
+
+#[test]
+fn overpaying_a_token_breaks_conservation() {
+    let (witnesses, mut settlement, root, nullifier_root) = cycle();
+    settlement.transferBalances[1].amount += U256::from(1u64);
+
+    assert_eq!(
+        rejection(&witnesses, &settlement, root, nullifier_root),
+        Some(BatchRejection {
+            index: 1,
+            reason: OrderRejection::FlowNotConserved,
+        })
+    );
+}
+
+fn swap(amount: i64, zero_for_one: bool, sqrt_price_limit_x96: U160) -> SwapBalance {
+    SwapBalance {
+        amountSpecified: I256::try_from(amount).unwrap() * I256::try_from(ether(1)).unwrap(),
+        zeroForOne: zero_for_one,
+        sqrtPriceLimitX96: sqrt_price_limit_x96,
+    }
+}
+
+#[test]
+fn swaps_sell_collected_inputs() {
+    // A single order routed through the pool: its A is sold for the B it receives,
+    // at no less than 2.25 B per A, so for at least 11.25 B
+    let (witnesses, root, nullifier_root) = witnesses(&[order(1, TOKEN_A, TOKEN_B, 5, 10)]);
+    let limit = U160::from(3u64) << 95;
+    let mut settlement = Settlement {
+        currency0: TOKEN_A.into(),
+        currency1: TOKEN_B.into(),
+        transferBalances: vec![transfer(10, TOKEN_B, 1)],
+        swapBalances: vec![swap(-5, true, limit)],
+    };
+    assert!(rejection(&witnesses, &settlement, root, nullifier_root).is_none());
+
+    // Selling less than was collected leaves A unaccounted for
+    settlement.swapBalances = vec![swap(-4, true, limit)];
+    assert_eq!(
+        rejection(&witnesses, &settlement, root, nullifier_root),
+        Some(BatchRejection {
+            index: 0,
+            reason: OrderRejection::FlowNotConserved,
+        })
+    );
+
+    // Exact-output swaps, swaps without a price limit and swaps of a token no
+    // order paid in are rejected
+    for swap in [
+        swap(5, true, limit),
+        swap(-5, true, U160::ZERO),
+        swap(-5, false, limit),
+    ] {
+        settlement.swapBalances = vec![swap];
+        assert_eq!(
+            rejection(&witnesses, &settlement, root, nullifier_root),
+            Some(BatchRejection {
+                index: 0,
+                reason: OrderRejection::InvalidSwap,
+            })
+        );
+    }
+}
+
+#[test]
+fn payouts_in_the_bought_token_are_bounded_by_the_swap() {
+    let (matched, root, nullifier_root) = witnesses(&[order(1, TOKEN_A, TOKEN_B, 5, 10)]);
+    let mut settlement = Settlement {
+        currency0: TOKEN_A.into(),
+        currency1: TOKEN_B.into(),
+        transferBalances: vec![transfer(10_000, TOKEN_B, 1)],
+        swapBalances: vec![swap(-5, true, U160::from(3u64) << 95)],
+    };
+
+    // 5 A bring in at least 11.25 B, so 10k B would come out of other users' claims
+    for amount in [10_000, 12] {
+        settlement.transferBalances = vec![transfer(amount, TOKEN_B, 1)];
+        assert_eq!(
+            rejection(&matched, &settlement, root, nullifier_root),
+            Some(BatchRejection {
+                index: 0,
+                reason: OrderRejection::FlowNotConserved,
+            })
+        );
+    }
+    settlement.transferBalances = vec![transfer(11, TOKEN_B, 1)];
+    assert!(rejection(&matched, &settlement, root, nullifier_root).is_none());
+
+    // Selling currency1 at no more than 4 B per A: 20 B bring in at least 5 A
+    let (matched, root, nullifier_root) = witnesses(&[order(1, TOKEN_B, TOKEN_A, 20, 5)]);
+    let mut settlement = Settlement {
+        currency0: TOKEN_A.into(),
+        currency1: TOKEN_B.into(),
+        transferBalances: vec![transfer(5, TOKEN_A, 1)],
+        swapBalances: vec![swap(-20, false, U160::from(1u64) << 97)],
+    };
+    assert!(rejection(&matched, &settlement, root, nullifier_root).is_none());
+    settlement.transferBalances = vec![transfer(6, TOKEN_A, 1)];
+    assert_eq!(
+        rejection(&matched, &settlement, root, nullifier_root),
+        Some(BatchRejection {
+            index: 0,
+            reason: OrderRejection::FlowNotConserved,
+        })
+    );
+}
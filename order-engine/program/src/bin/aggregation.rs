@@ -0,0 +1,39 @@
+//! Aggregation program.
+//!
+//! Verifies compressed proofs of the order program recursively and commits the
+//! public values of every one of them, so many independently proven orders cost
+//! a single Groth16 verification on-chain.
+#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+use alloy_sol_types::SolType;
+use fibonacci_lib::{try_aggregate_orders, vkey_digest, AggregatePublicValues};
+use sha2::{Digest, Sha256};
+
+pub fn main() {
+    // === INPUTS ===
+    // Verifying key digest of the order program (`vk.hash_u32()`), shared by every proof
+    let order_vkey = sp1_zkvm::io::read::<[u32; 8]>();
+    // Public values of each order proof; the proofs themselves are supplied by the host
+    let public_values = sp1_zkvm::io::read::<Vec<Vec<u8>>>();
+
+    // === VERIFICATION ===
+
+    // Each proof is genuine for exactly these public values
+    for values in &public_values {
+        let digest: [u8; 32] = Sha256::digest(values).into();
+        sp1_zkvm::lib::verify::verify_sp1_proof(&order_vkey, &digest);
+    }
+
+    // ...which belong to enforced orders with pairwise distinct nullifiers
+    let orders = match try_aggregate_orders(&public_values) {
+        Ok(orders) => orders,
+        Err(rejection) => panic!("aggregate rejected: {}", rejection),
+    };
+
+    // === PUBLIC OUTPUTS ===
+    sp1_zkvm::io::commit_slice(&AggregatePublicValues::abi_encode(&AggregatePublicValues {
+        orderVKeyDigest: vkey_digest(&order_vkey).into(),
+        orders,
+    }));
+}
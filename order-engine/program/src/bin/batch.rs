@@ -0,0 +1,65 @@
+//! Batch variant of the order program.
+//!
+//! Validates every order of a batch against one Merkle root, nullifier root and
+//! market snapshot, and commits the per-order public values together, so a whole
+//! batch costs a single proof verification on-chain.
+#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+use alloy_sol_types::SolType;
+use fibonacci_lib::{
+    try_verify_batch, BatchCheckResult, BatchPublicValues, MarketConditions, OrderWitness,
+    ValidationMode,
+};
+
+pub fn main() {
+    // === MODE ===
+    let mode = sp1_zkvm::io::read::<ValidationMode>();
+
+    // === PUBLIC INPUTS (shared by every order) ===
+    let market_conditions = sp1_zkvm::io::read::<MarketConditions>();
+    let merkle_root = sp1_zkvm::io::read::<[u8; 32]>();
+    let nullifier_root = sp1_zkvm::io::read::<[u8; 32]>();
+
+    // === PER-ORDER INPUTS ===
+    // Each witness carries its public nullifier hash alongside the private inputs
+    let witnesses = sp1_zkvm::io::read::<Vec<OrderWitness>>();
+
+    // === VERIFICATION ===
+
+    // Every order passes the single-order checks and no nullifier repeats
+    let result = try_verify_batch(
+        &witnesses,
+        &market_conditions,
+        &merkle_root,
+        &nullifier_root,
+    );
+
+    let orders = match mode {
+        // Report the first rejected order without committing any order data
+        ValidationMode::Check => {
+            let result = match result {
+                Ok(_) => BatchCheckResult {
+                    index: 0,
+                    reason: 0,
+                },
+                Err(rejection) => BatchCheckResult {
+                    index: rejection.index,
+                    reason: rejection.reason.code(),
+                },
+            };
+            sp1_zkvm::io::commit_slice(&BatchCheckResult::abi_encode(&result));
+            return;
+        }
+        // Fail closed: one invalid order means no proof for the batch
+        ValidationMode::Enforce => match result {
+            Ok(orders) => orders,
+            Err(rejection) => panic!("batch rejected: {}", rejection),
+        },
+    };
+
+    // === PUBLIC OUTPUTS ===
+    sp1_zkvm::io::commit_slice(&BatchPublicValues::abi_encode(&BatchPublicValues {
+        orders,
+    }));
+}
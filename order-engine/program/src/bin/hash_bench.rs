@@ -0,0 +1,40 @@
+//! Cycle-count benchmark of the hash backends inside the zkVM.
+//!
+//! Runs the hashes one order proof needs (order, nullifier and commitment hash plus a
+//! Merkle path of the given depth) under each backend, wrapped in SP1 cycle trackers.
+#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+use std::hint::black_box;
+
+use fibonacci_lib::{KeccakHasher, OrderData, OrderHasher, PoseidonHasher, Sha256Hasher, U256};
+
+fn run<H: OrderHasher>(order: &OrderData, nullifier: &[u8; 32], balance: U256, depth: u32) {
+    let order_hash = H::hash_order(order);
+    black_box(H::nullifier_hash(nullifier));
+    let commitment_hash = H::commitment_hash(&order_hash, nullifier, &order.token_in, balance);
+    let mut node = H::merkle_leaf(&commitment_hash);
+    for _ in 0..depth {
+        node = H::merkle_node(&node, nullifier);
+    }
+    black_box(node);
+}
+
+pub fn main() {
+    let order = sp1_zkvm::io::read::<OrderData>();
+    let nullifier = sp1_zkvm::io::read::<[u8; 32]>();
+    let balance = sp1_zkvm::io::read::<U256>();
+    let depth = sp1_zkvm::io::read::<u32>();
+
+    println!("cycle-tracker-report-start: sha256");
+    run::<Sha256Hasher>(&order, &nullifier, balance, depth);
+    println!("cycle-tracker-report-end: sha256");
+
+    println!("cycle-tracker-report-start: keccak");
+    run::<KeccakHasher>(&order, &nullifier, balance, depth);
+    println!("cycle-tracker-report-end: keccak");
+
+    println!("cycle-tracker-report-start: poseidon");
+    run::<PoseidonHasher>(&order, &nullifier, balance, depth);
+    println!("cycle-tracker-report-end: poseidon");
+}
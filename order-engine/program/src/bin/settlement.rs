@@ -0,0 +1,77 @@
+//! Settlement variant of the order program.
+//!
+//! Proves that the transfer and swap arrays the operator hands to
+//! `DarkCoWHook.settleBalances` fill every matched order on its own terms and
+//! conserve the tokens the orders pay in, and commits a hash of those arrays
+//! so the contract can tie the settlement it executes to the proof. It also
+//! inserts the orders' nullifiers into the spent-nullifier tree and commits
+//! the new root, which the contract moves to after settling.
+#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+use alloy_sol_types::SolType;
+use fibonacci_lib::{
+    try_verify_settlement, BatchCheckResult, MarketConditions, NullifierInsertionProof,
+    OrderWitness, Settlement, SettlementPublicValues, ValidationMode,
+};
+
+pub fn main() {
+    // === MODE ===
+    let mode = sp1_zkvm::io::read::<ValidationMode>();
+
+    // === PUBLIC INPUTS (shared by every order) ===
+    let market_conditions = sp1_zkvm::io::read::<MarketConditions>();
+    let merkle_root = sp1_zkvm::io::read::<[u8; 32]>();
+    let nullifier_root = sp1_zkvm::io::read::<[u8; 32]>();
+
+    // === PER-ORDER INPUTS ===
+    let witnesses = sp1_zkvm::io::read::<Vec<OrderWitness>>();
+    // Spends each order's nullifier in turn, starting from `nullifier_root`
+    let insertions = sp1_zkvm::io::read::<Vec<NullifierInsertionProof>>();
+
+    // === SETTLEMENT ===
+    // ABI-encoded, exactly as the operator computed it for the hook
+    let settlement_bytes = sp1_zkvm::io::read::<Vec<u8>>();
+    let settlement =
+        Settlement::abi_decode(&settlement_bytes).expect("settlement is not ABI-encoded");
+
+    // === VERIFICATION ===
+
+    // Every order passes the batch checks and transfer `i` fills order `i`;
+    // swaps only sell collected inputs and token flows are conserved; the
+    // nullifiers go into the spent-nullifier tree
+    let result = try_verify_settlement(
+        &witnesses,
+        &insertions,
+        &settlement,
+        &market_conditions,
+        &merkle_root,
+        &nullifier_root,
+    );
+
+    let public_values = match mode {
+        // Report the first rejected order or swap without committing any order data
+        ValidationMode::Check => {
+            let result = match result {
+                Ok(_) => BatchCheckResult {
+                    index: 0,
+                    reason: 0,
+                },
+                Err(rejection) => BatchCheckResult {
+                    index: rejection.index,
+                    reason: rejection.reason.code(),
+                },
+            };
+            sp1_zkvm::io::commit_slice(&BatchCheckResult::abi_encode(&result));
+            return;
+        }
+        // Fail closed: no proof can exist for an unsound settlement
+        ValidationMode::Enforce => match result {
+            Ok(public_values) => public_values,
+            Err(rejection) => panic!("settlement rejected: {}", rejection),
+        },
+    };
+
+    // === PUBLIC OUTPUTS ===
+    sp1_zkvm::io::commit_slice(&SettlementPublicValues::abi_encode(&public_values));
+}
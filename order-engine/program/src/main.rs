@@ -1,77 +1,83 @@
 #![no_main]
 sp1_zkvm::entrypoint!(main);
 
+use alloy_sol_types::SolType;
 use fibonacci_lib::{
-    compute_commitment_hash, compute_nullifier_hash, validate_order,
-    verify_commitment_merkle_proof, verify_nullifier_order, MarketConditions, OrderCommitment,
-    OrderData,
+    try_verify_order_witness, MarketConditions, NullifierNonMembershipProof, OrderCheckResult,
+    OrderData, OrderPublicValues, OrderRejection, OrderWitness, ValidationMode, U256,
 };
 
 pub fn main() {
+    // === MODE ===
+    let mode = sp1_zkvm::io::read::<ValidationMode>();
+
     // === PUBLIC INPUTS ===
     let market_conditions = sp1_zkvm::io::read::<MarketConditions>();
     let merkle_root = sp1_zkvm::io::read::<[u8; 32]>(); // Public Merkle root
     let expected_nullifier_hash = sp1_zkvm::io::read::<[u8; 32]>(); // Public nullifier hash
+    let nullifier_root = sp1_zkvm::io::read::<[u8; 32]>(); // Public spent-nullifier root
 
     // === PRIVATE INPUTS ===
     let order_data = sp1_zkvm::io::read::<OrderData>();
     let nullifier = sp1_zkvm::io::read::<[u8; 32]>(); // Private nullifier secret
-    let user_balance = sp1_zkvm::io::read::<u64>(); // Private balance
+    let balance_asset = sp1_zkvm::io::read::<[u8; 20]>(); // Private token the balance is held in
+    let user_balance = sp1_zkvm::io::read::<U256>(); // Private balance
     let merkle_siblings = sp1_zkvm::io::read::<Vec<[u8; 32]>>(); // Private Merkle proof
-    let merkle_indices = sp1_zkvm::io::read::<Vec<u8>>(); // Private Merkle path
-
-    // === NULLIFIER VERIFICATION ===
-
-    // 1. Verify nullifier hash matches expected (prevents replay attacks)
-    let computed_nullifier_hash = compute_nullifier_hash(&nullifier);
-    let nullifier_hash_valid = computed_nullifier_hash == expected_nullifier_hash;
-
-    // 2. Compute commitment hash from private inputs
-    let commitment_hash = compute_commitment_hash(&order_data, &nullifier, user_balance);
-
-    // 3. Create commitment struct for verification
-    let commitment = OrderCommitment {
-        order_data: order_data.clone(),
+    let leaf_index = sp1_zkvm::io::read::<u64>(); // Private leaf position (packed path bits)
+    let non_membership = sp1_zkvm::io::read::<NullifierNonMembershipProof>(); // Private witness
+    let fill_amount = sp1_zkvm::io::read::<U256>(); // Private part of the order filled now
+    let change_nullifier = sp1_zkvm::io::read::<[u8; 32]>(); // Private secret of the change note
+    let change_order = sp1_zkvm::io::read::<Option<OrderData>>(); // Private order of the change note
+
+    let witness = OrderWitness {
+        nullifier_hash: expected_nullifier_hash,
+        order: order_data,
         nullifier,
+        balance_asset,
         balance: user_balance,
+        siblings: merkle_siblings,
+        leaf_index,
+        non_membership,
+        fill_amount,
+        change_nullifier,
+        change_order,
     };
 
-    // === MERKLE TREE VERIFICATION ===
-
-    // 4. Verify commitment is in the Merkle tree
-    let merkle_valid = verify_commitment_merkle_proof(
-        &commitment_hash,
-        &merkle_siblings,
-        &merkle_indices,
-        &merkle_root,
-    );
-
-    // === ORDER VALIDATION ===
-
-    // 5. Verify order execution conditions
-    let order_executable = verify_nullifier_order(
-        &commitment,
-        &market_conditions,
-        &commitment_hash,
-        &expected_nullifier_hash,
-    );
-
-    // === FINAL VERIFICATION ===
-
-    let final_validity = nullifier_hash_valid && merkle_valid && order_executable;
+    // === VERIFICATION ===
+
+    // Nullifier hash, spent-nullifier non-membership, commitment Merkle path
+    // (exactly TREE_DEPTH long), balance asset, fill amount (all of it for a
+    // fill-or-kill order) and order conditions (deadline, start time, trigger
+    // and price); the first failed check is the reported rejection
+    let result =
+        try_verify_order_witness(&witness, &market_conditions, &merkle_root, &nullifier_root);
+
+    let public_values = match mode {
+        // Report why the order would be rejected without committing any order data
+        ValidationMode::Check => {
+            let result = OrderCheckResult {
+                reason: result.err().map_or(0, OrderRejection::code),
+            };
+            sp1_zkvm::io::commit_slice(&OrderCheckResult::abi_encode(&result));
+            return;
+        }
+        // Fail closed: no proof can exist for an invalid order
+        ValidationMode::Enforce => match result {
+            Ok(public_values) => public_values,
+            Err(reason) => panic!("order rejected: {}", reason),
+        },
+    };
 
     // === PUBLIC OUTPUTS ===
 
-    // Commit the validity result
-    sp1_zkvm::io::commit(&final_validity);
-
-    // Commit the nullifier hash (to be stored on-chain for replay prevention)
-    sp1_zkvm::io::commit(&computed_nullifier_hash);
-
-    // Commit the wallet address (for order execution)
-    sp1_zkvm::io::commit(&order_data.wallet_address);
-
-    // Commit order amounts (for swap execution)
-    sp1_zkvm::io::commit(&order_data.amount_in);
-    sp1_zkvm::io::commit(&order_data.min_amount_out);
+    // Commit everything as a single ABI-encoded struct so the same bytes can be
+    // decoded on-chain and by the host:
+    // - the validity result (always true: invalid orders never reach this point)
+    // - the nullifier hash (inserted into the spent-nullifier tree once settled)
+    // - the wallet address (for order execution)
+    // - the fill amounts (for swap execution)
+    // - the tree root, nullifier root, token pair, deadline and market snapshot the proof
+    //   was made against
+    // - the change commitment (appended to the commitment tree once settled)
+    sp1_zkvm::io::commit_slice(&OrderPublicValues::abi_encode(&public_values));
 }
@@ -0,0 +1,127 @@
+//! Proves orders one by one as compressed proofs of the order program, then
+//! aggregates them into a single proof with the aggregation program.
+//!
+//! Usage:
+//! ```shell
+//! RUST_LOG=info cargo run --release --bin aggregate -- --execute
+//! RUST_LOG=info cargo run --release --bin aggregate -- --prove
+//! ```
+
+use alloy_sol_types::SolType;
+use fibonacci_lib::{
+    vkey_digest, AggregatePublicValues, MarketConditions, NullifierTree, OrderWitness,
+    ValidationMode, U256,
+};
+use fibonacci_script::{sample_notes, spend_notes, Args};
+use sp1_sdk::{include_elf, HashableKey, ProverClient, SP1Proof, SP1Stdin};
+use std::error::Error;
+
+/// The ELF for the single-order program whose proofs are aggregated.
+pub const ORDER_ELF: &[u8] = include_elf!("fibonacci-program");
+
+/// The ELF for the aggregation program.
+pub const AGGREGATION_ELF: &[u8] = include_elf!("aggregation");
+
+/// Order program input for one order, in the order the guest reads it
+fn order_stdin(
+    witness: &OrderWitness,
+    market_conditions: &MarketConditions,
+    tree_root: &[u8; 32],
+    nullifier_root: &[u8; 32],
+) -> SP1Stdin {
+    let mut stdin = SP1Stdin::new();
+    stdin.write(&ValidationMode::Enforce);
+    // public
+    stdin.write(market_conditions);
+    stdin.write(tree_root);
+    stdin.write(&witness.nullifier_hash);
+    stdin.write(nullifier_root);
+    // private
+    stdin.write(&witness.order);
+    stdin.write(&witness.nullifier);
+    stdin.write(&witness.balance_asset);
+    stdin.write(&witness.balance);
+    stdin.write(&witness.siblings);
+    stdin.write(&witness.leaf_index);
+    stdin.write(&witness.non_membership);
+    stdin.write(&witness.fill_amount);
+    stdin.write(&witness.change_nullifier);
+    stdin.write(&witness.change_order);
+    stdin
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    sp1_sdk::utils::setup_logger();
+    dotenv::dotenv().ok();
+
+    let args = Args::parse_mode();
+
+    let market_conditions = MarketConditions {
+        current_price: U256::from(2050000000u64), // $2050
+        block_timestamp: 1735600000u64,
+    };
+
+    let spent_nullifiers = NullifierTree::new();
+    let nullifier_root = spent_nullifiers.root();
+    let (witnesses, tree_root) = spend_notes(sample_notes(), &spent_nullifiers)?;
+
+    let client = ProverClient::from_env();
+    let (order_pk, order_vk) = client.setup(ORDER_ELF);
+
+    // Prove each order on its own; compressed proofs can be verified inside SP1
+    let mut order_proofs = Vec::new();
+    for witness in &witnesses {
+        let stdin = order_stdin(witness, &market_conditions, &tree_root, &nullifier_root);
+        let proof = client.prove(&order_pk, &stdin).compressed().run()?;
+        client.verify(&proof, &order_vk)?;
+        println!("Order {} proven", order_proofs.len());
+        order_proofs.push(proof);
+    }
+
+    // The aggregation program reads the order vkey and public values, and
+    // verifies the proofs supplied alongside them
+    let mut stdin = SP1Stdin::new();
+    stdin.write(&order_vk.hash_u32());
+    stdin.write(
+        &order_proofs
+            .iter()
+            .map(|proof| proof.public_values.to_vec())
+            .collect::<Vec<_>>(),
+    );
+    for proof in order_proofs {
+        let SP1Proof::Compressed(proof) = proof.proof else {
+            return Err("order proof is not compressed".into());
+        };
+        stdin.write_proof(*proof, order_vk.vk.clone());
+    }
+
+    let public_values = if args.execute {
+        let (output, report) = client.execute(AGGREGATION_ELF, &stdin).run()?;
+        println!(
+            "Aggregation executed ({} cycles)",
+            report.total_instruction_count()
+        );
+        AggregatePublicValues::abi_decode(output.as_slice())?
+    } else {
+        let (pk, vk) = client.setup(AGGREGATION_ELF);
+        let proof = client.prove(&pk, &stdin).groth16().run()?;
+        client.verify(&proof, &vk)?;
+        println!("Groth16 proof verified");
+        println!("Aggregation program vkey: {}", vk.bytes32());
+        AggregatePublicValues::abi_decode(proof.public_values.as_slice())?
+    };
+
+    assert_eq!(
+        public_values.orderVKeyDigest,
+        vkey_digest(&order_vk.hash_u32())
+    );
+    println!("Order vkey digest: {}", public_values.orderVKeyDigest);
+    for (index, order) in public_values.orders.iter().enumerate() {
+        println!(
+            "  Order {}: wallet {} sells {} for at least {} (nullifier {})",
+            index, order.walletAddress, order.amountIn, order.minAmountOut, order.nullifierHash
+        );
+    }
+
+    Ok(())
+}
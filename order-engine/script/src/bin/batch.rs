@@ -0,0 +1,91 @@
+//! Proves a batch of orders in a single SP1 run with the batch order program.
+//!
+//! Usage:
+//! ```shell
+//! RUST_LOG=info cargo run --release --bin batch -- --execute
+//! RUST_LOG=info cargo run --release --bin batch -- --prove
+//! ```
+
+use alloy_sol_types::SolType;
+use fibonacci_lib::{
+    BatchCheckResult, BatchPublicValues, MarketConditions, NullifierTree, OrderRejection,
+    ValidationMode, U256,
+};
+use fibonacci_script::{sample_notes, spend_notes, Args};
+use sp1_sdk::{include_elf, ProverClient, SP1Stdin};
+use std::error::Error;
+
+/// The ELF for the batch order program.
+pub const BATCH_ELF: &[u8] = include_elf!("batch");
+
+fn main() -> Result<(), Box<dyn Error>> {
+    sp1_sdk::utils::setup_logger();
+    dotenv::dotenv().ok();
+
+    let args = Args::parse_mode();
+
+    let market_conditions = MarketConditions {
+        current_price: U256::from(2050000000u64), // $2050
+        block_timestamp: 1735600000u64,
+    };
+
+    let spent_nullifiers = NullifierTree::new();
+    let nullifier_root = spent_nullifiers.root();
+    let (witnesses, tree_root) = spend_notes(sample_notes(), &spent_nullifiers)?;
+
+    println!("Batch of {} orders", witnesses.len());
+
+    let build_stdin = |mode: ValidationMode| {
+        let mut stdin = SP1Stdin::new();
+        stdin.write(&mode);
+        stdin.write(&market_conditions);
+        stdin.write(&tree_root);
+        stdin.write(&nullifier_root);
+        stdin.write(&witnesses);
+        stdin
+    };
+
+    let client = ProverClient::from_env();
+
+    // Check mode names the first rejected order without panicking
+    let (check_output, report) = client
+        .execute(BATCH_ELF, &build_stdin(ValidationMode::Check))
+        .run()?;
+    let check = BatchCheckResult::abi_decode(check_output.as_slice())?;
+    if check.reason != 0 {
+        match OrderRejection::from_code(check.reason) {
+            Some(rejection) => println!("Order {} rejected: {}", check.index, rejection),
+            None => println!("Order {} rejected: code {}", check.index, check.reason),
+        }
+        return Ok(());
+    }
+    println!(
+        "All orders valid ({} cycles)",
+        report.total_instruction_count()
+    );
+
+    let public_values = if args.execute {
+        let (output, _) = client
+            .execute(BATCH_ELF, &build_stdin(ValidationMode::Enforce))
+            .run()?;
+        BatchPublicValues::abi_decode(output.as_slice())?
+    } else {
+        let (pk, vk) = client.setup(BATCH_ELF);
+        let proof = client
+            .prove(&pk, &build_stdin(ValidationMode::Enforce))
+            .groth16()
+            .run()?;
+        client.verify(&proof, &vk)?;
+        println!("Groth16 proof verified");
+        BatchPublicValues::abi_decode(proof.public_values.as_slice())?
+    };
+
+    for (index, order) in public_values.orders.iter().enumerate() {
+        println!(
+            "  Order {}: wallet {} sells {} for at least {} (nullifier {})",
+            index, order.walletAddress, order.amountIn, order.minAmountOut, order.nullifierHash
+        );
+    }
+
+    Ok(())
+}
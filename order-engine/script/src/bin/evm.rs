@@ -12,7 +12,11 @@
 
 use alloy_sol_types::SolType;
 use clap::{Parser, ValueEnum};
-use fibonacci_lib::PublicValuesStruct;
+use fibonacci_lib::{
+    create_order_commitment, derive_change_nullifier, hash_order, IncrementalMerkleTree,
+    MarketConditions, NullifierTree, OrderData, OrderKind, OrderPublicValues, OrderSide,
+    TimeInForce, ValidationMode, U256,
+};
 use serde::{Deserialize, Serialize};
 use sp1_sdk::{
     include_elf, HashableKey, ProverClient, SP1ProofWithPublicValues, SP1Stdin, SP1VerifyingKey,
@@ -26,8 +30,6 @@ pub const FIBONACCI_ELF: &[u8] = include_elf!("fibonacci-program");
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct EVMArgs {
-    #[arg(long, default_value = "20")]
-    n: u32,
     #[arg(long, value_enum, default_value = "groth16")]
     system: ProofSystem,
 }
@@ -42,10 +44,20 @@ enum ProofSystem {
 /// A fixture that can be used to test the verification of SP1 zkVM proofs inside Solidity.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct SP1FibonacciProofFixture {
-    a: u32,
-    b: u32,
-    n: u32,
+struct SP1OrderProofFixture {
+    valid: bool,
+    nullifier_hash: String,
+    wallet_address: String,
+    amount_in: String,
+    min_amount_out: String,
+    tree_root: String,
+    nullifier_root: String,
+    change_commitment: String,
+    token_in: String,
+    token_out: String,
+    deadline: u64,
+    current_price: String,
+    block_timestamp: u64,
     vkey: String,
     public_values: String,
     proof: String,
@@ -64,11 +76,69 @@ fn main() {
     // Setup the program.
     let (pk, vk) = client.setup(FIBONACCI_ELF);
 
-    // Setup the inputs.
+    // Setup the inputs: Alice's demo order, committed as the first leaf of the tree.
+    let alice_secret = [1u8; 32];
+    let alice_balance = U256::from(10000000000000000000u64);
+    let alice_order = OrderData {
+        wallet_address: [1u8; 20],
+        token_in: [0xAu8; 20],
+        token_out: [0xBu8; 20],
+        amount_in: U256::from(5000000000000000000u64),
+        min_amount_out: U256::from(10000000000u64),
+        target_price: U256::from(2000000000u64),
+        deadline: 1735689600u64,
+        side: OrderSide::Sell,
+        base_decimals: 18,
+        kind: OrderKind::Limit,
+        time_in_force: TimeInForce::Gtc,
+        valid_after: 0,
+    };
+    let market_conditions = MarketConditions {
+        current_price: U256::from(2050000000u64),
+        block_timestamp: 1735600000u64,
+    };
+
+    let (alice_commitment, alice_nullifier) = create_order_commitment(
+        &alice_order,
+        &alice_secret,
+        &alice_order.token_in,
+        alice_balance,
+        &hash_order(&alice_order),
+    );
+    let mut tree = IncrementalMerkleTree::default();
+    let alice_leaf = tree
+        .append(alice_nullifier.commitment_hash)
+        .expect("empty tree has room");
+    let tree_root = tree.root();
+    let siblings = tree.proof(alice_leaf).expect("leaf was just appended");
+    let spent_nullifiers = NullifierTree::new();
+    let nullifier_root = spent_nullifiers.root();
+    let non_membership = spent_nullifiers
+        .non_membership_proof(&alice_nullifier.nullifier_hash)
+        .expect("no nullifier has been spent");
+
     let mut stdin = SP1Stdin::new();
-    stdin.write(&args.n);
+    stdin.write(&ValidationMode::Enforce);
+    stdin.write(&market_conditions);
+    stdin.write(&tree_root);
+    stdin.write(&alice_nullifier.nullifier_hash);
+    stdin.write(&nullifier_root);
+    stdin.write(&alice_order);
+    stdin.write(&alice_commitment.nullifier);
+    stdin.write(&alice_commitment.balance_asset);
+    stdin.write(&alice_balance);
+    stdin.write(&siblings);
+    stdin.write(&alice_leaf);
+    stdin.write(&non_membership);
+    // Fill the whole order; the rest of the balance goes to a change note under
+    // the same terms
+    stdin.write(&alice_order.amount_in);
+    stdin.write(&derive_change_nullifier(
+        &alice_commitment.nullifier,
+        &alice_nullifier.commitment_hash,
+    ));
+    stdin.write(&Some(alice_order.clone()));
 
-    println!("n: {}", args.n);
     println!("Proof System: {:?}", args.system);
 
     // Generate the proof based on the selected proof system.
@@ -89,13 +159,23 @@ fn create_proof_fixture(
 ) {
     // Deserialize the public values.
     let bytes = proof.public_values.as_slice();
-    let PublicValuesStruct { n, a, b } = PublicValuesStruct::abi_decode(bytes).unwrap();
+    let public_values = OrderPublicValues::abi_decode(bytes).unwrap();
 
     // Create the testing fixture so we can test things end-to-end.
-    let fixture = SP1FibonacciProofFixture {
-        a,
-        b,
-        n,
+    let fixture = SP1OrderProofFixture {
+        valid: public_values.valid,
+        nullifier_hash: public_values.nullifierHash.to_string(),
+        wallet_address: public_values.walletAddress.to_string(),
+        amount_in: public_values.amountIn.to_string(),
+        min_amount_out: public_values.minAmountOut.to_string(),
+        tree_root: public_values.treeRoot.to_string(),
+        nullifier_root: public_values.nullifierRoot.to_string(),
+        change_commitment: public_values.changeCommitment.to_string(),
+        token_in: public_values.tokenIn.to_string(),
+        token_out: public_values.tokenOut.to_string(),
+        deadline: public_values.deadline,
+        current_price: public_values.currentPrice.to_string(),
+        block_timestamp: public_values.blockTimestamp,
         vkey: vk.bytes32().to_string(),
         public_values: format!("0x{}", hex::encode(bytes)),
         proof: format!("0x{}", hex::encode(proof.bytes())),
@@ -0,0 +1,63 @@
+//! Reports the zkVM cycle cost of each hash backend, to pick one per deployment.
+//!
+//! Usage:
+//! ```shell
+//! RUST_LOG=info cargo run --release --bin hash-bench -- --depth 20
+//! ```
+
+use clap::Parser;
+use fibonacci_lib::{OrderData, OrderKind, OrderSide, TimeInForce, U256};
+use sp1_sdk::{include_elf, ProverClient, SP1Stdin};
+
+/// The ELF for the hash benchmark guest.
+pub const HASH_BENCH_ELF: &[u8] = include_elf!("hash_bench");
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Merkle path length to hash per order
+    #[arg(long, default_value = "20")]
+    depth: u32,
+}
+
+fn main() {
+    sp1_sdk::utils::setup_logger();
+    let args = Args::parse();
+
+    let order = OrderData {
+        wallet_address: [1u8; 20],
+        token_in: [0xAu8; 20],
+        token_out: [0xBu8; 20],
+        amount_in: U256::from(5000000000000000000u64),
+        min_amount_out: U256::from(10000000000u64),
+        target_price: U256::from(2000000000u64),
+        deadline: 1735689600u64,
+        side: OrderSide::Sell,
+        base_decimals: 18,
+        kind: OrderKind::Limit,
+        time_in_force: TimeInForce::Gtc,
+        valid_after: 0,
+    };
+
+    let mut stdin = SP1Stdin::new();
+    stdin.write(&order);
+    stdin.write(&[3u8; 32]);
+    stdin.write(&U256::from(10000000000000000000u64));
+    stdin.write(&args.depth);
+
+    let client = ProverClient::from_env();
+    let (_, report) = client
+        .execute(HASH_BENCH_ELF, &stdin)
+        .run()
+        .expect("failed to execute hash benchmark");
+
+    println!("Cycles per order proof (Merkle depth {}):", args.depth);
+    for backend in ["sha256", "keccak", "poseidon"] {
+        let cycles = report
+            .cycle_tracker
+            .get(backend)
+            .copied()
+            .unwrap_or_default();
+        println!("  {:<10} {}", backend, cycles);
+    }
+}
@@ -14,11 +14,12 @@
 use alloy_sol_types::SolType;
 use clap::Parser;
 use fibonacci_lib::{
-    compute_nullifier_hash, create_order_commitment, hash_order, verify_commitment_merkle_proof,
-    verify_nullifier_order, MarketConditions, NullifierData, OrderCommitment, OrderData,
+    compute_nullifier_hash, create_order_commitment, derive_change_nullifier, hash_order,
+    try_verify_nullifier_order, verify_commitment_merkle_proof, verify_nullifier_non_membership,
+    IncrementalMerkleTree, MarketConditions, NullifierData, NullifierTree, OrderCheckResult,
+    OrderCommitment, OrderData, OrderKind, OrderPublicValues, OrderRejection, OrderSide,
+    TimeInForce, ValidationMode, U256,
 };
-use sha2::{Digest, Sha256};
-use std::collections::HashMap;
 use std::error::Error;
 
 use sp1_sdk::{include_elf, ProverClient, SP1Stdin};
@@ -39,115 +40,23 @@ struct Args {
     demo: String,
 }
 
-/// Merkle tree for commitments (not individual balances)
-pub struct CommitmentMerkleTree {
-    leaves: Vec<[u8; 32]>,
-    users: Vec<String>, // Track which user corresponds to each commitment
-}
-
-impl CommitmentMerkleTree {
-    pub fn new() -> Self {
-        Self {
-            leaves: Vec::new(),
-            users: Vec::new(),
-        }
-    }
-
-    pub fn add_commitment(&mut self, commitment_hash: [u8; 32], user_name: String) {
-        self.leaves.push(commitment_hash);
-        self.users.push(user_name);
-    }
-
-    pub fn build_tree(&self) -> ([u8; 32], Vec<Vec<[u8; 32]>>) {
-        if self.leaves.is_empty() {
-            return ([0u8; 32], vec![]);
-        }
-
-        let mut levels = vec![self.leaves.clone()];
-        let mut current_level = self.leaves.clone();
-
-        while current_level.len() > 1 {
-            let mut next_level = Vec::new();
-
-            for i in (0..current_level.len()).step_by(2) {
-                let left = current_level[i];
-                let right = if i + 1 < current_level.len() {
-                    current_level[i + 1]
-                } else {
-                    left
-                };
-
-                let parent = self.hash_pair(left, right);
-                next_level.push(parent);
-            }
-
-            levels.push(next_level.clone());
-            current_level = next_level;
-        }
-
-        let root = current_level[0];
-        (root, levels)
-    }
-
-    pub fn generate_proof(
-        &self,
-        commitment_hash: [u8; 32],
-    ) -> Result<(Vec<[u8; 32]>, Vec<u8>), Box<dyn Error>> {
-        let leaf_index = self
-            .leaves
-            .iter()
-            .position(|&leaf| leaf == commitment_hash)
-            .ok_or("Commitment not found in tree")?;
-
-        let (_, levels) = self.build_tree();
-        let mut siblings = Vec::new();
-        let mut indices = Vec::new();
-        let mut current_index = leaf_index;
-
-        for level in 0..(levels.len() - 1) {
-            let sibling_index = if current_index % 2 == 0 {
-                current_index + 1
-            } else {
-                current_index - 1
-            };
-
-            if sibling_index < levels[level].len() {
-                siblings.push(levels[level][sibling_index]);
-            } else {
-                siblings.push(levels[level][current_index]);
-            }
-
-            indices.push((current_index % 2) as u8);
-            current_index /= 2;
-        }
-
-        Ok((siblings, indices))
-    }
-
-    fn hash_pair(&self, left: [u8; 32], right: [u8; 32]) -> [u8; 32] {
-        let mut hasher = Sha256::new();
-        hasher.update(b"MERKLE_NODE");
-        hasher.update(&left);
-        hasher.update(&right);
-        hasher.finalize().into()
-    }
-}
-
 /// User with their secret and orders
 #[derive(Debug, Clone)]
 struct User {
     name: String,
     secret: [u8; 32],
-    balance: u64,
+    balance_asset: [u8; 20],
+    balance: U256,
     orders: Vec<OrderData>,
     commitments: Vec<(OrderCommitment, NullifierData)>,
 }
 
 impl User {
-    fn new(name: String, secret: [u8; 32], balance: u64) -> Self {
+    fn new(name: String, secret: [u8; 32], balance_asset: [u8; 20], balance: U256) -> Self {
         Self {
             name,
             secret,
+            balance_asset,
             balance,
             orders: Vec::new(),
             commitments: Vec::new(),
@@ -156,8 +65,13 @@ impl User {
 
     fn create_order(&mut self, order: OrderData) -> (OrderCommitment, NullifierData) {
         let order_context = hash_order(&order);
-        let (commitment, nullifier_data) =
-            create_order_commitment(&order, &self.secret, self.balance, &order_context);
+        let (commitment, nullifier_data) = create_order_commitment(
+            &order,
+            &self.secret,
+            &self.balance_asset,
+            self.balance,
+            &order_context,
+        );
 
         self.orders.push(order);
         self.commitments
@@ -174,9 +88,19 @@ fn demonstrate_nullifier_flow() -> Result<(), Box<dyn Error>> {
     // Step 1: Create users with their secrets
     println!("\n👤 Step 1: Creating Users with Secrets");
 
-    let mut alice = User::new("Alice".to_string(), [1u8; 32], 10000000000000000000u64); // 10 ETH
-    let mut bob = User::new("Bob".to_string(), [2u8; 32], 15000000000u64); // 15k USDC
-    let mut charlie = User::new("Charlie".to_string(), [3u8; 32], 5000000000u64); // 5k USDC
+    let alice_balance = U256::from(10000000000000000000u64); // 10 ETH
+    let bob_balance = U256::from(15000000000u64); // 15k USDC
+    let charlie_balance = U256::from(5000000000u64); // 5k USDC
+
+    // Each balance is committed together with the token it is held in
+    let mut alice = User::new("Alice".to_string(), [1u8; 32], [0xAu8; 20], alice_balance);
+    let mut bob = User::new("Bob".to_string(), [2u8; 32], [0xBu8; 20], bob_balance);
+    let mut charlie = User::new(
+        "Charlie".to_string(),
+        [3u8; 32],
+        [0xBu8; 20],
+        charlie_balance,
+    );
 
     println!("  Alice: 10 ETH (secret: [1u8; 32])");
     println!("  Bob: 15k USDC (secret: [2u8; 32])");
@@ -187,22 +111,32 @@ fn demonstrate_nullifier_flow() -> Result<(), Box<dyn Error>> {
 
     let alice_order = OrderData {
         wallet_address: [1u8; 20],
-        token_in: [0xAu8; 20],             // ETH
-        token_out: [0xBu8; 20],            // USDC
-        amount_in: 5000000000000000000u64, // 5 ETH
-        min_amount_out: 10000000000u64,    // 10k USDC
-        target_price: 2000000000u64,       // $2000/ETH
+        token_in: [0xAu8; 20],                         // ETH
+        token_out: [0xBu8; 20],                        // USDC
+        amount_in: U256::from(5000000000000000000u64), // 5 ETH
+        min_amount_out: U256::from(10000000000u64),    // 10k USDC
+        target_price: U256::from(2000000000u64),       // $2000/ETH
         deadline: 1735689600u64,
+        side: OrderSide::Sell,
+        base_decimals: 18,
+        kind: OrderKind::Limit,
+        time_in_force: TimeInForce::Gtc,
+        valid_after: 0,
     };
 
     let bob_order = OrderData {
         wallet_address: [2u8; 20],
-        token_in: [0xBu8; 20],                  // USDC
-        token_out: [0xAu8; 20],                 // ETH
-        amount_in: 8000000000u64,               // 8k USDC
-        min_amount_out: 3800000000000000000u64, // 3.8 ETH
-        target_price: 2100000000u64,            // Max $2100/ETH
+        token_in: [0xBu8; 20],                              // USDC
+        token_out: [0xAu8; 20],                             // ETH
+        amount_in: U256::from(8000000000u64),               // 8k USDC
+        min_amount_out: U256::from(3800000000000000000u64), // 3.8 ETH
+        target_price: U256::from(2100000000u64),            // Max $2100/ETH
         deadline: 1735689600u64,
+        side: OrderSide::Buy,
+        base_decimals: 18,
+        kind: OrderKind::Limit,
+        time_in_force: TimeInForce::Gtc,
+        valid_after: 0,
     };
 
     // Create commitments
@@ -232,11 +166,11 @@ fn demonstrate_nullifier_flow() -> Result<(), Box<dyn Error>> {
     // Step 3: Build commitment Merkle tree
     println!("\n🌳 Step 3: Building Commitment Tree");
 
-    let mut commitment_tree = CommitmentMerkleTree::new();
-    commitment_tree.add_commitment(alice_nullifier.commitment_hash, alice.name.clone());
-    commitment_tree.add_commitment(bob_nullifier.commitment_hash, bob.name.clone());
+    let mut commitment_tree = IncrementalMerkleTree::default();
+    let alice_leaf = commitment_tree.append(alice_nullifier.commitment_hash)?;
+    let bob_leaf = commitment_tree.append(bob_nullifier.commitment_hash)?;
 
-    let (tree_root_v1, _) = commitment_tree.build_tree();
+    let tree_root_v1 = commitment_tree.root();
     println!("  Tree v1 Root: {:02x?}", &tree_root_v1[..8]);
     println!("  Commitments: Alice, Bob");
 
@@ -245,19 +179,24 @@ fn demonstrate_nullifier_flow() -> Result<(), Box<dyn Error>> {
 
     let charlie_order = OrderData {
         wallet_address: [3u8; 20],
-        token_in: [0xBu8; 20],                  // USDC
-        token_out: [0xAu8; 20],                 // ETH
-        amount_in: 3000000000u64,               // 3k USDC
-        min_amount_out: 1400000000000000000u64, // 1.4 ETH
-        target_price: 2150000000u64,            // Max $2150/ETH
+        token_in: [0xBu8; 20],                              // USDC
+        token_out: [0xAu8; 20],                             // ETH
+        amount_in: U256::from(3000000000u64),               // 3k USDC
+        min_amount_out: U256::from(1400000000000000000u64), // 1.4 ETH
+        target_price: U256::from(2150000000u64),            // Max $2150/ETH
         deadline: 1735689600u64,
+        side: OrderSide::Buy,
+        base_decimals: 18,
+        kind: OrderKind::Limit,
+        time_in_force: TimeInForce::Gtc,
+        valid_after: 0,
     };
 
     let (charlie_commitment, charlie_nullifier) = charlie.create_order(charlie_order);
 
     // Add Charlie to the tree
-    commitment_tree.add_commitment(charlie_nullifier.commitment_hash, charlie.name.clone());
-    let (tree_root_v2, _) = commitment_tree.build_tree();
+    commitment_tree.append(charlie_nullifier.commitment_hash)?;
+    let tree_root_v2 = commitment_tree.root();
 
     println!("  Charlie order: 3k USDC → ETH at max $2150");
     println!("  Tree v2 Root: {:02x?}", &tree_root_v2[..8]);
@@ -268,30 +207,29 @@ fn demonstrate_nullifier_flow() -> Result<(), Box<dyn Error>> {
     println!("\n⚡ Step 5: Alice Executes Order (Against New Tree)");
 
     let market_conditions = MarketConditions {
-        current_price: 2050000000u64, // $2050 (favorable for Alice)
+        current_price: U256::from(2050000000u64), // $2050 (favorable for Alice)
         block_timestamp: 1735600000u64,
     };
 
     // Generate proof for Alice's commitment in the NEW tree
-    let (alice_siblings, alice_indices) =
-        commitment_tree.generate_proof(alice_nullifier.commitment_hash)?;
+    let alice_siblings = commitment_tree.proof(alice_leaf)?;
 
     println!("  Market price: $2050 (Alice's target: $2000) ✅");
     println!(
-        "  Alice's proof: {} siblings, {} indices",
+        "  Alice's proof: {} siblings, leaf index {}",
         alice_siblings.len(),
-        alice_indices.len()
+        alice_leaf
     );
 
     // Verify Alice's order can execute
     let alice_merkle_valid = verify_commitment_merkle_proof(
         &alice_nullifier.commitment_hash,
         &alice_siblings,
-        &alice_indices,
+        alice_leaf,
         &tree_root_v2, // Using NEW tree root!
     );
 
-    let alice_order_valid = verify_nullifier_order(
+    let alice_order_check = try_verify_nullifier_order(
         &alice_commitment,
         &market_conditions,
         &alice_nullifier.commitment_hash,
@@ -299,90 +237,112 @@ fn demonstrate_nullifier_flow() -> Result<(), Box<dyn Error>> {
     );
 
     println!("  Merkle proof valid: {}", alice_merkle_valid);
-    println!("  Order execution valid: {}", alice_order_valid);
+    println!("  Order execution check: {:?}", alice_order_check);
     println!("  ✅ Alice can execute even though tree changed!");
 
     // Step 6: Demonstrate nullifier replay prevention
     println!("\n🛡️  Step 6: Nullifier Replay Prevention");
 
-    // Simulate on-chain nullifier storage
-    let mut used_nullifiers: HashMap<[u8; 32], String> = HashMap::new();
+    // Spent nullifiers live in an indexed Merkle tree; only its root goes on-chain
+    let mut spent_nullifiers = NullifierTree::new();
 
     // Alice executes her order
-    used_nullifiers.insert(alice_nullifier.nullifier_hash, "Alice's order".to_string());
+    spent_nullifiers.insert(alice_nullifier.nullifier_hash)?;
     println!(
-        "  Alice's nullifier stored on-chain: {:02x?}",
+        "  Alice's nullifier marked spent: {:02x?}",
         &alice_nullifier.nullifier_hash[..8]
     );
+    println!("  Nullifier root: {:02x?}", &spent_nullifiers.root()[..8]);
 
-    // Try to replay Alice's nullifier
-    let is_replay = used_nullifiers.contains_key(&alice_nullifier.nullifier_hash);
+    // Try to replay Alice's nullifier: no non-membership witness exists any more
+    let is_replay = spent_nullifiers
+        .non_membership_proof(&alice_nullifier.nullifier_hash)
+        .is_err();
     println!("  Replay attempt detected: {}", is_replay);
     println!("  🚨 Alice cannot execute the same order twice!");
 
     // Step 7: Bob can still execute independently
     println!("\n🔄 Step 7: Bob Executes Independently");
 
-    let bob_not_used = !used_nullifiers.contains_key(&bob_nullifier.nullifier_hash);
+    let bob_witness = spent_nullifiers.non_membership_proof(&bob_nullifier.nullifier_hash)?;
+    let bob_not_used = verify_nullifier_non_membership(
+        &bob_nullifier.nullifier_hash,
+        &bob_witness,
+        &spent_nullifiers.root(),
+    );
     println!("  Bob's nullifier unused: {}", bob_not_used);
     println!("  ✅ Bob can execute his order independently");
 
     // Step 8: Add even more users to show scalability
     println!("\n📈 Step 8: Adding More Users");
 
-    let diana = User::new("Diana".to_string(), [4u8; 32], 10000000000000000000u64); // 10 ETH
-    let eve = User::new("Eve".to_string(), [5u8; 32], 25000000000u64); // 25k USDC
+    let diana_balance = U256::from(25000000000000000000u128); // 25 ETH (beyond u64::MAX wei)
+    let eve_balance = U256::from(25000000000u64); // 25k USDC
+
+    let diana = User::new("Diana".to_string(), [4u8; 32], [0xAu8; 20], diana_balance);
+    let eve = User::new("Eve".to_string(), [5u8; 32], [0xBu8; 20], eve_balance);
 
     // Create their orders and add to tree
     let diana_order = OrderData {
         wallet_address: [4u8; 20],
-        token_in: [0xAu8; 20],             // ETH
-        token_out: [0xBu8; 20],            // USDC
-        amount_in: 8000000000000000000u64, // 8 ETH
-        min_amount_out: 16000000000u64,    // 16k USDC
-        target_price: 2000000000u64,       // $2000/ETH
+        token_in: [0xAu8; 20],                         // ETH
+        token_out: [0xBu8; 20],                        // USDC
+        amount_in: U256::from(8000000000000000000u64), // 8 ETH
+        min_amount_out: U256::from(16000000000u64),    // 16k USDC
+        target_price: U256::from(2000000000u64),       // $2000/ETH
         deadline: 1735689600u64,
+        side: OrderSide::Sell,
+        base_decimals: 18,
+        kind: OrderKind::Limit,
+        time_in_force: TimeInForce::Gtc,
+        valid_after: 0,
     };
 
     let (_, diana_nullifier) = create_order_commitment(
         &diana_order,
         &diana.secret,
+        &diana.balance_asset,
         diana.balance,
         &hash_order(&diana_order),
     );
 
     let eve_order = OrderData {
         wallet_address: [5u8; 20],
-        token_in: [0xBu8; 20],                  // USDC
-        token_out: [0xAu8; 20],                 // ETH
-        amount_in: 12000000000u64,              // 12k USDC
-        min_amount_out: 5500000000000000000u64, // 5.5 ETH
-        target_price: 2200000000u64,            // Max $2200/ETH
+        token_in: [0xBu8; 20],                              // USDC
+        token_out: [0xAu8; 20],                             // ETH
+        amount_in: U256::from(12000000000u64),              // 12k USDC
+        min_amount_out: U256::from(5500000000000000000u64), // 5.5 ETH
+        target_price: U256::from(2200000000u64),            // Max $2200/ETH
         deadline: 1735689600u64,
+        side: OrderSide::Buy,
+        base_decimals: 18,
+        kind: OrderKind::Limit,
+        time_in_force: TimeInForce::Gtc,
+        valid_after: 0,
     };
 
     let (_, eve_nullifier) = create_order_commitment(
         &eve_order,
         &eve.secret,
+        &eve.balance_asset,
         eve.balance,
         &hash_order(&eve_order),
     );
 
-    commitment_tree.add_commitment(diana_nullifier.commitment_hash, "Diana".to_string());
-    commitment_tree.add_commitment(eve_nullifier.commitment_hash, "Eve".to_string());
+    commitment_tree.append(diana_nullifier.commitment_hash)?;
+    commitment_tree.append(eve_nullifier.commitment_hash)?;
 
-    let (tree_root_v3, _) = commitment_tree.build_tree();
-    println!("  Added Diana (10 ETH) and Eve (25k USDC)");
+    let tree_root_v3 = commitment_tree.root();
+    println!("  Added Diana (25 ETH) and Eve (25k USDC)");
     println!("  Tree v3 Root: {:02x?}", &tree_root_v3[..8]);
     println!("  Total users: Alice, Bob, Charlie, Diana, Eve");
 
     // Verify Bob can still execute against the newest tree
-    let (bob_siblings_v3, bob_indices_v3) =
-        commitment_tree.generate_proof(bob_nullifier.commitment_hash)?;
+    let bob_siblings_v3 = commitment_tree.proof(bob_leaf)?;
     let bob_still_valid = verify_commitment_merkle_proof(
         &bob_nullifier.commitment_hash,
         &bob_siblings_v3,
-        &bob_indices_v3,
+        bob_leaf,
         &tree_root_v3,
     );
 
@@ -411,72 +371,127 @@ fn run_sp1_nullifier_test() -> Result<(), Box<dyn Error>> {
         wallet_address: [1u8; 20],
         token_in: [0xAu8; 20],
         token_out: [0xBu8; 20],
-        amount_in: 5000000000000000000u64,
-        min_amount_out: 10000000000u64,
-        target_price: 2000000000u64,
+        amount_in: U256::from(5000000000000000000u64),
+        min_amount_out: U256::from(10000000000u64),
+        target_price: U256::from(2000000000u64),
         deadline: 1735689600u64,
+        side: OrderSide::Sell,
+        base_decimals: 18,
+        kind: OrderKind::Limit,
+        time_in_force: TimeInForce::Gtc,
+        valid_after: 0,
     };
 
     let market_conditions = MarketConditions {
-        current_price: 2050000000u64,
+        current_price: U256::from(2050000000u64),
         block_timestamp: 1735600000u64,
     };
 
-    let alice_balance = 10000000000000000000u64;
+    let alice_balance = U256::from(10000000000000000000u64);
     let order_context = hash_order(&alice_order);
 
-    let (alice_commitment, alice_nullifier) =
-        create_order_commitment(&alice_order, &alice_secret, alice_balance, &order_context);
+    let (alice_commitment, alice_nullifier) = create_order_commitment(
+        &alice_order,
+        &alice_secret,
+        &alice_order.token_in, // the balance is held in ETH, the token the order sells
+        alice_balance,
+        &order_context,
+    );
 
     // Build tree with Alice's commitment
-    let mut tree = CommitmentMerkleTree::new();
-    tree.add_commitment(alice_nullifier.commitment_hash, "Alice".to_string());
-
-    let (tree_root, _) = tree.build_tree();
-    let (siblings, indices) = tree.generate_proof(alice_nullifier.commitment_hash)?;
+    let mut tree = IncrementalMerkleTree::default();
+    let alice_leaf = tree.append(alice_nullifier.commitment_hash)?;
+
+    let tree_root = tree.root();
+    let siblings = tree.proof(alice_leaf)?;
+
+    // Nothing has been spent yet, so Alice's nullifier is absent from the spent tree
+    let spent_nullifiers = NullifierTree::new();
+    let nullifier_root = spent_nullifiers.root();
+    let non_membership = spent_nullifiers.non_membership_proof(&alice_nullifier.nullifier_hash)?;
+
+    // Fill the whole order; the other 5 ETH of the note goes to a change note,
+    // which Alice offers on the same terms once this order closes
+    let fill_amount = alice_order.amount_in;
+    let change_nullifier = derive_change_nullifier(
+        &alice_commitment.nullifier,
+        &alice_nullifier.commitment_hash,
+    );
+    let change_order = Some(alice_order.clone());
 
     println!("  Order: 5 ETH → USDC at $2000 target");
     println!("  Market: $2050 (favorable)");
-    println!("  Balance: 10 ETH (sufficient)");
+    println!("  Balance: 10 ETH (5 ETH change)");
 
     // Setup SP1 inputs
-    let mut stdin = SP1Stdin::new();
-
-    // Public inputs
-    stdin.write(&market_conditions);
-    stdin.write(&tree_root);
-    stdin.write(&alice_nullifier.nullifier_hash);
+    let build_stdin = |mode: ValidationMode| {
+        let mut stdin = SP1Stdin::new();
+        stdin.write(&mode);
+
+        // Public inputs
+        stdin.write(&market_conditions);
+        stdin.write(&tree_root);
+        stdin.write(&alice_nullifier.nullifier_hash);
+        stdin.write(&nullifier_root);
+
+        // Private inputs
+        stdin.write(&alice_order);
+        stdin.write(&alice_commitment.nullifier);
+        stdin.write(&alice_commitment.balance_asset);
+        stdin.write(&alice_balance);
+        stdin.write(&siblings);
+        stdin.write(&alice_leaf);
+        stdin.write(&non_membership);
+        stdin.write(&fill_amount);
+        stdin.write(&change_nullifier);
+        stdin.write(&change_order);
+        stdin
+    };
 
-    // Private inputs
-    stdin.write(&alice_order);
-    stdin.write(&alice_commitment.nullifier);
-    stdin.write(&alice_balance);
-    stdin.write(&siblings);
-    stdin.write(&indices);
+    // Check mode reports why an order would be rejected without panicking
+    println!("  🔄 Checking order in SP1 program...");
+    let (check_output, _) = client
+        .execute(FIBONACCI_ELF, &build_stdin(ValidationMode::Check))
+        .run()?;
+    let check = OrderCheckResult::abi_decode(check_output.as_slice())?;
+
+    if check.reason != 0 {
+        match OrderRejection::from_code(check.reason) {
+            Some(rejection) => println!("  ❌ Order validation failed: {}", rejection),
+            None => println!("  ❌ Order validation failed: code {}", check.reason),
+        }
+        return Ok(());
+    }
 
     println!("  🔄 Executing SP1 program...");
-    let (mut output, report) = client.execute(FIBONACCI_ELF, &stdin).run()?;
+    let (output, report) = client
+        .execute(FIBONACCI_ELF, &build_stdin(ValidationMode::Enforce))
+        .run()?;
 
-    // Read outputs
-    let is_valid = output.read::<bool>();
-    let nullifier_hash = output.read::<[u8; 32]>();
-    let wallet_address = output.read::<[u8; 20]>();
-    let amount_in = output.read::<u64>();
-    let min_amount_out = output.read::<u64>();
+    // Decode the ABI-encoded public values
+    let public_values = OrderPublicValues::abi_decode(output.as_slice())?;
 
     println!("  ✅ SP1 Results:");
-    println!("    Valid: {}", is_valid);
-    println!("    Nullifier: {:02x?}", &nullifier_hash[..8]);
-    println!("    Wallet: {:02x?}", &wallet_address[..4]);
-    println!("    Amount in: {}", amount_in);
-    println!("    Min out: {}", min_amount_out);
+    println!("    Valid: {}", public_values.valid);
+    println!("    Nullifier: {:02x?}", &public_values.nullifierHash[..8]);
+    println!("    Wallet: {:02x?}", &public_values.walletAddress[..4]);
+    println!("    Amount in: {}", public_values.amountIn);
+    println!("    Min out: {}", public_values.minAmountOut);
+    println!("    Tree root: {:02x?}", &public_values.treeRoot[..8]);
+    println!(
+        "    Nullifier root: {:02x?}",
+        &public_values.nullifierRoot[..8]
+    );
+    println!(
+        "    Change commitment: {:02x?}",
+        &public_values.changeCommitment[..8]
+    );
+    println!(
+        "    Market: {} @ {}",
+        public_values.currentPrice, public_values.blockTimestamp
+    );
     println!("    Cycles: {}", report.total_instruction_count());
-
-    if is_valid {
-        println!("  🎯 Order ready for execution!");
-    } else {
-        println!("  ❌ Order validation failed!");
-    }
+    println!("  🎯 Order ready for execution!");
 
     Ok(())
 }
@@ -489,59 +504,92 @@ fn run_sp1_nullifier_prove() -> Result<(), Box<dyn Error>> {
 
     let (pk, vk) = client.setup(FIBONACCI_ELF);
 
-
-
     // Create test data
     let alice_secret = [1u8; 32];
     let alice_order = OrderData {
         wallet_address: [1u8; 20],
         token_in: [0xAu8; 20],
         token_out: [0xBu8; 20],
-        amount_in: 5000000000000000000u64,
-        min_amount_out: 10000000000u64,
-        target_price: 2000000000u64,
+        amount_in: U256::from(5000000000000000000u64),
+        min_amount_out: U256::from(10000000000u64),
+        target_price: U256::from(2000000000u64),
         deadline: 1735689600u64,
+        side: OrderSide::Sell,
+        base_decimals: 18,
+        kind: OrderKind::Limit,
+        time_in_force: TimeInForce::Gtc,
+        valid_after: 0,
     };
 
     let market_conditions = MarketConditions {
-        current_price: 2050000000u64,
+        current_price: U256::from(2050000000u64),
         block_timestamp: 1735600000u64,
     };
 
-    let alice_balance = 10000000000000000000u64;
+    let alice_balance = U256::from(10000000000000000000u64);
     let order_context = hash_order(&alice_order);
 
-    let (alice_commitment, alice_nullifier) =
-        create_order_commitment(&alice_order, &alice_secret, alice_balance, &order_context);
+    let (alice_commitment, alice_nullifier) = create_order_commitment(
+        &alice_order,
+        &alice_secret,
+        &alice_order.token_in, // the balance is held in ETH, the token the order sells
+        alice_balance,
+        &order_context,
+    );
 
     // Build tree with Alice's commitment
-    let mut tree = CommitmentMerkleTree::new();
-    tree.add_commitment(alice_nullifier.commitment_hash, "Alice".to_string());
-
-    let (tree_root, _) = tree.build_tree();
-    let (siblings, indices) = tree.generate_proof(alice_nullifier.commitment_hash)?;
+    let mut tree = IncrementalMerkleTree::default();
+    let alice_leaf = tree.append(alice_nullifier.commitment_hash)?;
+
+    let tree_root = tree.root();
+    let siblings = tree.proof(alice_leaf)?;
+
+    // Nothing has been spent yet, so Alice's nullifier is absent from the spent tree
+    let spent_nullifiers = NullifierTree::new();
+    let nullifier_root = spent_nullifiers.root();
+    let non_membership = spent_nullifiers.non_membership_proof(&alice_nullifier.nullifier_hash)?;
+
+    // Fill the whole order; the other 5 ETH of the note goes to a change note,
+    // which Alice offers on the same terms once this order closes
+    let fill_amount = alice_order.amount_in;
+    let change_nullifier = derive_change_nullifier(
+        &alice_commitment.nullifier,
+        &alice_nullifier.commitment_hash,
+    );
+    let change_order = Some(alice_order.clone());
 
     println!("  Order: 5 ETH → USDC at $2000 target");
     println!("  Market: $2050 (favorable)");
-    println!("  Balance: 10 ETH (sufficient)");
+    println!("  Balance: 10 ETH (5 ETH change)");
 
-    // Setup SP1 inputs
+    // Setup SP1 inputs; enforcing mode refuses to prove an invalid order
     let mut stdin = SP1Stdin::new();
+    stdin.write(&ValidationMode::Enforce);
 
     // Public inputs
     stdin.write(&market_conditions);
     stdin.write(&tree_root);
     stdin.write(&alice_nullifier.nullifier_hash);
+    stdin.write(&nullifier_root);
 
     // Private inputs
     stdin.write(&alice_order);
     stdin.write(&alice_commitment.nullifier);
+    stdin.write(&alice_commitment.balance_asset);
     stdin.write(&alice_balance);
     stdin.write(&siblings);
-    stdin.write(&indices);
+    stdin.write(&alice_leaf);
+    stdin.write(&non_membership);
+    stdin.write(&fill_amount);
+    stdin.write(&change_nullifier);
+    stdin.write(&change_order);
+
+    let proof = client.prove(&pk, &stdin).groth16().run()?;
+    client.verify(&proof, &vk)?;
 
-    let mut proof = client.prove(&pk, &stdin).groth16().run()?;
+    let public_values = OrderPublicValues::abi_decode(proof.public_values.as_slice())?;
 
+    println!("    Valid: {}", public_values.valid);
     println!("    Proof: {:?}", proof);
     Ok(())
 }
@@ -0,0 +1,161 @@
+//! Proves a circular settlement of matched orders with the settlement program.
+//!
+//! Usage:
+//! ```shell
+//! RUST_LOG=info cargo run --release --bin settlement -- --execute
+//! RUST_LOG=info cargo run --release --bin settlement -- --prove
+//! ```
+
+use alloy_sol_types::SolType;
+use fibonacci_lib::{
+    BatchCheckResult, MarketConditions, NullifierTree, OrderRejection, Settlement,
+    SettlementPublicValues, TransferBalance, ValidationMode, U256,
+};
+use fibonacci_script::{sell_order, spend_notes, Args};
+use sp1_sdk::{include_elf, ProverClient, SP1Stdin};
+use std::error::Error;
+
+/// The ELF for the settlement program.
+pub const SETTLEMENT_ELF: &[u8] = include_elf!("settlement");
+
+const TOKEN_A: [u8; 20] = [0xAu8; 20];
+const TOKEN_B: [u8; 20] = [0xBu8; 20];
+const TOKEN_C: [u8; 20] = [0xCu8; 20];
+
+fn main() -> Result<(), Box<dyn Error>> {
+    sp1_sdk::utils::setup_logger();
+    dotenv::dotenv().ok();
+
+    let args = Args::parse_mode();
+
+    // The order program checks one market price against every target price
+    let market_conditions = MarketConditions {
+        current_price: U256::from(2050000000000000000u64), // 2.05 per whole input token
+        block_timestamp: 1735600000u64,
+    };
+
+    // A → B → C → A: each order is filled with the next order's input. Every token
+    // has 18 decimals and amounts are in whole tokens.
+    let one = U256::from(1000000000000000000u64);
+    let order = |wallet: u8, token_in, token_out, amount_in: u64, min_amount_out: u64| {
+        sell_order(
+            wallet,
+            token_in,
+            token_out,
+            U256::from(amount_in) * one,
+            U256::from(min_amount_out) * one,
+            // Output base units per whole input token
+            U256::from(min_amount_out) * one / U256::from(amount_in),
+        )
+    };
+    let orders = [
+        order(1, TOKEN_A, TOKEN_B, 5, 10),  // Alice: 5 A for 10 B
+        order(2, TOKEN_B, TOKEN_C, 10, 20), // Bob: 10 B for 20 C
+        order(3, TOKEN_C, TOKEN_A, 20, 5),  // Carol: 20 C for 5 A
+    ];
+
+    // What the matcher computes for the hook: order `i` receives order `i + 1`'s input
+    let settlement = Settlement {
+        currency0: TOKEN_A.into(),
+        currency1: TOKEN_B.into(),
+        transferBalances: orders
+            .iter()
+            .enumerate()
+            .map(|(i, order)| TransferBalance {
+                amount: orders[(i + 1) % orders.len()].amount_in,
+                currency: order.token_out.into(),
+                sender: order.wallet_address.into(),
+            })
+            .collect(),
+        swapBalances: vec![],
+    };
+
+    // Each note holds exactly its order's input
+    let notes = orders.iter().map(|order| {
+        (
+            [order.wallet_address[0]; 32],
+            order.amount_in,
+            order.clone(),
+        )
+    });
+    let mut spent_nullifiers = NullifierTree::new();
+    let nullifier_root = spent_nullifiers.root();
+    let (witnesses, tree_root) = spend_notes(notes, &spent_nullifiers)?;
+
+    // Settling spends every nullifier, in the order of the witnesses
+    let insertions = witnesses
+        .iter()
+        .map(|witness| spent_nullifiers.insert_with_proof(witness.nullifier_hash))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    println!(
+        "Settling {} orders with {} transfers and {} swaps",
+        witnesses.len(),
+        settlement.transferBalances.len(),
+        settlement.swapBalances.len()
+    );
+
+    let settlement_bytes = Settlement::abi_encode(&settlement);
+    let build_stdin = |mode: ValidationMode| {
+        let mut stdin = SP1Stdin::new();
+        stdin.write(&mode);
+        stdin.write(&market_conditions);
+        stdin.write(&tree_root);
+        stdin.write(&nullifier_root);
+        stdin.write(&witnesses);
+        stdin.write(&insertions);
+        stdin.write(&settlement_bytes);
+        stdin
+    };
+
+    let client = ProverClient::from_env();
+
+    // Check mode names the first rejected order or swap without panicking
+    let (check_output, report) = client
+        .execute(SETTLEMENT_ELF, &build_stdin(ValidationMode::Check))
+        .run()?;
+    let check = BatchCheckResult::abi_decode(check_output.as_slice())?;
+    if check.reason != 0 {
+        match OrderRejection::from_code(check.reason) {
+            Some(rejection) => println!("Entry {} rejected: {}", check.index, rejection),
+            None => println!("Entry {} rejected: code {}", check.index, check.reason),
+        }
+        return Ok(());
+    }
+    println!(
+        "Settlement valid ({} cycles)",
+        report.total_instruction_count()
+    );
+
+    let public_values = if args.execute {
+        let (output, _) = client
+            .execute(SETTLEMENT_ELF, &build_stdin(ValidationMode::Enforce))
+            .run()?;
+        SettlementPublicValues::abi_decode(output.as_slice())?
+    } else {
+        let (pk, vk) = client.setup(SETTLEMENT_ELF);
+        let proof = client
+            .prove(&pk, &build_stdin(ValidationMode::Enforce))
+            .groth16()
+            .run()?;
+        client.verify(&proof, &vk)?;
+        println!("Groth16 proof verified");
+        SettlementPublicValues::abi_decode(proof.public_values.as_slice())?
+    };
+
+    println!("Settlement hash: {}", public_values.settlementHash);
+    println!("New nullifier root: {}", public_values.newNullifierRoot);
+    for (index, order) in public_values.orders.iter().enumerate() {
+        println!(
+            "  Order {}: wallet {} sells {} of {} for at least {} of {}",
+            index,
+            order.walletAddress,
+            order.amountIn,
+            order.tokenIn,
+            order.minAmountOut,
+            order.tokenOut
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,138 @@
+//! Shared setup for the batch, settlement and aggregation scripts: their
+//! command line, sample orders and the witnesses that spend them.
+
+use clap::Parser;
+use fibonacci_lib::{
+    create_order_commitment, derive_change_nullifier, hash_order, IncrementalMerkleTree,
+    NullifierTree, OrderData, OrderKind, OrderSide, OrderWitness, TimeInForce, U256,
+};
+use std::error::Error;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    #[arg(long)]
+    pub execute: bool,
+
+    #[arg(long)]
+    pub prove: bool,
+}
+
+impl Args {
+    /// Parses the command line, exiting unless exactly one of `--execute` and
+    /// `--prove` is given
+    pub fn parse_mode() -> Self {
+        let args = Self::parse();
+        if args.execute == args.prove {
+            eprintln!("Error: You must specify either --execute or --prove");
+            std::process::exit(1);
+        }
+        args
+    }
+}
+
+/// A limit sell order, good till cancelled, of an 18-decimal token
+pub fn sell_order(
+    wallet: u8,
+    token_in: [u8; 20],
+    token_out: [u8; 20],
+    amount_in: U256,
+    min_amount_out: U256,
+    target_price: U256,
+) -> OrderData {
+    OrderData {
+        wallet_address: [wallet; 20],
+        token_in,
+        token_out,
+        amount_in,
+        min_amount_out,
+        target_price,
+        deadline: 1735689600u64,
+        side: OrderSide::Sell,
+        base_decimals: 18,
+        kind: OrderKind::Limit,
+        time_in_force: TimeInForce::Gtc,
+        valid_after: 0,
+    }
+}
+
+/// The two ETH → USDC sell notes, as `(secret, balance, order)`, that the batch
+/// and aggregation scripts prove
+pub fn sample_notes() -> [([u8; 32], U256, OrderData); 2] {
+    [
+        (
+            [1u8; 32],
+            U256::from(10000000000000000000u64), // 10 ETH
+            sell_order(
+                1,
+                [0xAu8; 20],
+                [0xBu8; 20],
+                U256::from(5000000000000000000u64), // 5 ETH
+                U256::from(10000000000u64),         // 10k USDC
+                U256::from(2000000000u64),          // $2000/ETH
+            ),
+        ),
+        (
+            [4u8; 32],
+            U256::from(25000000000000000000u128), // 25 ETH
+            sell_order(
+                4,
+                [0xAu8; 20],
+                [0xBu8; 20],
+                U256::from(8000000000000000000u64), // 8 ETH
+                U256::from(16000000000u64),         // 16k USDC
+                U256::from(2000000000u64),          // $2000/ETH
+            ),
+        ),
+    ]
+}
+
+/// Commits every `(secret, balance, order)` note into one tree and returns the
+/// witnesses spending them, with the tree root they are proven against.
+pub fn spend_notes(
+    notes: impl IntoIterator<Item = ([u8; 32], U256, OrderData)>,
+    spent_nullifiers: &NullifierTree,
+) -> Result<(Vec<OrderWitness>, [u8; 32]), Box<dyn Error>> {
+    // Every commitment goes into one tree, so all the orders share one root
+    let mut tree = IncrementalMerkleTree::default();
+    let mut committed = Vec::new();
+    for (secret, balance, order) in notes {
+        let (commitment, nullifier_data) = create_order_commitment(
+            &order,
+            &secret,
+            &order.token_in,
+            balance,
+            &hash_order(&order),
+        );
+        let leaf_index = tree.append(nullifier_data.commitment_hash)?;
+        committed.push((commitment, nullifier_data, leaf_index));
+    }
+
+    let witnesses = committed
+        .into_iter()
+        .map(|(commitment, nullifier_data, leaf_index)| {
+            // Each order is filled in full; any balance beyond it goes to a change note
+            // offered again on the same terms
+            let fill_amount = commitment.order_data.amount_in;
+            let change_order = Some(commitment.order_data.clone());
+            let change_nullifier =
+                derive_change_nullifier(&commitment.nullifier, &nullifier_data.commitment_hash);
+            Ok(OrderWitness {
+                nullifier_hash: nullifier_data.nullifier_hash,
+                order: commitment.order_data,
+                nullifier: commitment.nullifier,
+                balance_asset: commitment.balance_asset,
+                balance: commitment.balance,
+                siblings: tree.proof(leaf_index)?,
+                leaf_index,
+                non_membership: spent_nullifiers
+                    .non_membership_proof(&nullifier_data.nullifier_hash)?,
+                fill_amount,
+                change_nullifier,
+                change_order,
+            })
+        })
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+    Ok((witnesses, tree.root()))
+}
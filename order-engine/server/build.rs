@@ -1,5 +1,12 @@
-use sp1_build::build_program_with_args;
+use sp1_build::{BuildArgs, build_program_with_args};
 
 fn main() {
-    build_program_with_args("../program", Default::default())
+    // The guest must hash with the same backend as this crate
+    let mut args = BuildArgs::default();
+    for feature in ["keccak", "poseidon"] {
+        if std::env::var(format!("CARGO_FEATURE_{}", feature.to_uppercase())).is_ok() {
+            args.features.push(feature.to_string());
+        }
+    }
+    build_program_with_args("../program", args)
 }
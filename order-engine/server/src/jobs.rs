@@ -0,0 +1,173 @@
+//! Proving jobs
+//!
+//! A proof takes minutes, so handlers queue the work and return a job ID
+//! straight away. Jobs run on blocking threads, at most `workers` at a time,
+//! and clients poll `GET /jobs/:id` for the state and result. Every job goes
+//! through the [`JobStore`], so a proof outlives the request that asked for it
+//! and unfinished jobs run again after a restart (see [`crate::store`]).
+
+use crate::store::JobStore;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{marker::PhantomData, sync::Arc};
+use tokio::sync::Semaphore;
+
+pub type JobId = u64;
+
+/// Where a job is; `done`, `failed` and `cancelled` are final
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum JobStatus {
+    /// Waiting for a free worker
+    Queued,
+    /// Running the order program in check mode
+    Executing,
+    /// Generating the proof
+    Proving,
+    Done {
+        result: serde_json::Value,
+    },
+    Failed {
+        error: String,
+    },
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            Self::Done { .. } | Self::Failed { .. } | Self::Cancelled
+        )
+    }
+
+    /// The `state` tag on its own
+    pub fn state(&self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Executing => "executing",
+            Self::Proving => "proving",
+            Self::Done { .. } => "done",
+            Self::Failed { .. } => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Work the queue can run, persist and resume
+pub trait Job: Serialize + DeserializeOwned + Send + 'static {
+    /// The inputs as they are kept once the job finishes, without private data
+    fn redacted(&self) -> serde_json::Value;
+
+    /// Runs on a prover worker
+    fn run(&self, job: &JobHandle) -> Result<JobOutput, String>;
+}
+
+/// What a finished job leaves behind
+pub struct JobOutput {
+    /// Body of the job's `done` status
+    pub result: serde_json::Value,
+    pub public_values: Vec<u8>,
+    /// The proof, serialized as JSON
+    pub proof: Vec<u8>,
+}
+
+/// A running job's hold on its own status
+pub struct JobHandle {
+    id: JobId,
+    store: Arc<JobStore>,
+}
+
+impl JobHandle {
+    /// Moves the job on to its next stage, or fails once it has been cancelled
+    ///
+    /// A stage already running is never interrupted, so jobs call this between
+    /// stages to stop as soon as they can.
+    pub fn advance(&self, status: JobStatus) -> Result<(), String> {
+        match self.store.advance(self.id, status) {
+            Ok(Some(status)) if !status.is_final() => Ok(()),
+            Ok(_) => Err("job cancelled".to_string()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+}
+
+/// Runs jobs from the store on a pool of workers
+pub struct JobQueue<J> {
+    store: Arc<JobStore>,
+    workers: Arc<Semaphore>,
+    job: PhantomData<fn() -> J>,
+}
+
+impl<J: Job> JobQueue<J> {
+    pub fn new(store: JobStore, workers: usize) -> Self {
+        Self {
+            store: Arc::new(store),
+            workers: Arc::new(Semaphore::new(workers)),
+            job: PhantomData,
+        }
+    }
+
+    pub fn store(&self) -> &JobStore {
+        &self.store
+    }
+
+    /// Stores `job`, queues it and returns its ID
+    pub fn submit(self: &Arc<Self>, job: J) -> anyhow::Result<JobId> {
+        let id = self
+            .store
+            .insert(job.redacted(), &serde_json::to_vec(&job)?)?;
+        self.spawn(id, job);
+        Ok(id)
+    }
+
+    /// Queues every job that had not finished when the server stopped, and
+    /// returns how many run again
+    pub fn resume(self: &Arc<Self>) -> anyhow::Result<usize> {
+        let unfinished = self.store.unfinished()?;
+        let count = unfinished.len();
+        for (id, payload) in unfinished {
+            match serde_json::from_slice::<J>(&payload) {
+                Ok(job) => {
+                    // A stage cut short by the stop starts over
+                    self.store.advance(id, JobStatus::Queued)?;
+                    self.spawn(id, job);
+                }
+                Err(err) => self
+                    .store
+                    .finish(id, Err(format!("cannot resume job: {err}")))?,
+            }
+        }
+        Ok(count)
+    }
+
+    /// Cancels a job that has not finished yet and returns its status after the call
+    ///
+    /// A queued job never starts. A running job stops before its next stage and
+    /// whatever the current stage produces is dropped.
+    pub fn cancel(&self, id: JobId) -> anyhow::Result<Option<JobStatus>> {
+        self.store.cancel(id)
+    }
+
+    fn spawn(self: &Arc<Self>, id: JobId, job: J) {
+        let queue = Arc::clone(self);
+        tokio::spawn(async move {
+            // The semaphore is never closed
+            let _worker = queue.workers.clone().acquire_owned().await.unwrap();
+            let handle = JobHandle {
+                id,
+                store: Arc::clone(&queue.store),
+            };
+            if handle.advance(JobStatus::Executing).is_err() {
+                return;
+            }
+
+            let outcome = match tokio::task::spawn_blocking(move || job.run(&handle)).await {
+                Ok(outcome) => outcome,
+                Err(err) => Err(format!("job panicked: {err}")),
+            };
+            if let Err(err) = queue.store.finish(id, outcome) {
+                tracing::error!("cannot store the outcome of job {id}: {err}");
+            }
+        });
+    }
+}
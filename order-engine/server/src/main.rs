@@ -1,15 +1,30 @@
-use axum::{Json, Router, extract::State, http::StatusCode, response::IntoResponse, routing::post};
+use alloy_sol_types::{SolCall, SolType, sol};
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::IntoResponse,
+    routing::{get, post},
+};
 use base64::{Engine as _, engine::general_purpose};
 use hex::FromHex;
-use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use sp1_sdk::{
-    EnvProver, ProverClient, SP1ProofWithPublicValues, SP1ProvingKey, SP1Stdin, SP1VerifyingKey,
-    include_elf, utils,
+    EnvProver, HashableKey, ProverClient, SP1Proof, SP1ProofWithPublicValues, SP1ProvingKey,
+    SP1Stdin, SP1VerifyingKey, include_elf, utils,
 };
 extern crate std;
 
-use std::{net::SocketAddr, sync::Arc};
+mod jobs;
+mod store;
+
+use jobs::{Job, JobHandle, JobId, JobOutput, JobQueue, JobStatus};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, OnceLock, RwLock},
+};
+use store::{JobRecord, JobStore, TreeLog};
 
 /// ──────────────────────────────────────────────────────────────
 ///  ⚙️  SP1 guest ELF compiled from your nullifier validation program
@@ -17,9 +32,30 @@ use std::{net::SocketAddr, sync::Arc};
 /// ──────────────────────────────────────────────────────────────
 pub const ELF: &[u8] = include_elf!("fibonacci-program");
 
+/// Aggregation program: verifies compressed proofs of `ELF` recursively.
+pub const AGGREGATION_ELF: &[u8] = include_elf!("aggregation");
+
+/// Program IDs: the guest binaries `ELF` and `AGGREGATION_ELF` are built from
+const ORDER_PROGRAM_ID: &str = "fibonacci-program";
+const AGGREGATION_PROGRAM_ID: &str = "aggregation";
+
+/// Layout of `ProveResponse` and `AggregateResponse`; bump on any change to them
+const RESPONSE_VERSION: u32 = 4;
+
+sol! {
+    /// `OrderServiceManager` entry points operators submit proofs to
+    function verifyOrderProof(bytes publicValues, bytes proofBytes);
+    function verifyAggregatedProof(bytes publicValues, bytes proofBytes);
+}
+
 /// ────────────────  Types that already live in your guest crate  ────────────────
 /// Bring them in so we can build identical Rust structs on the host.
-use fibonacci_lib::{MarketConditions, OrderData};
+use fibonacci_lib::{
+    AggregatePublicValues, IncrementalMerkleTree, MarketConditions, NullifierTree,
+    OrderCheckResult, OrderData, OrderKind, OrderPublicValues, OrderRejection, OrderSide,
+    OrderWitness, TimeInForce, U256, ValidationMode, compute_commitment_hash,
+    derive_change_nullifier, try_aggregate_orders, vkey_digest,
+};
 
 /// ────────────────  Shared app-level state  ────────────────
 #[derive(Clone)]
@@ -27,18 +63,205 @@ struct AppState {
     client: Arc<EnvProver>,
     pk: Arc<SP1ProvingKey>,
     vk: Arc<SP1VerifyingKey>,
+    aggregation_pk: Arc<SP1ProvingKey>,
+    aggregation_vk: Arc<SP1VerifyingKey>,
+    tree: Arc<RwLock<IncrementalMerkleTree>>,
+    nullifiers: Arc<RwLock<NullifierTree>>,
+    // Leaves of both trees on disk, which they are rebuilt from at startup
+    tree_log: Arc<TreeLog>,
+    jobs: Arc<JobQueue<ProofJob>>,
+    proof_modes: Arc<ProofModes>,
+    // Bearer token of the indexer allowed to write the trees; unset disables writes
+    indexer_token: Option<Arc<str>>,
+    // `GET /program` and `GET /vkey` bodies, built once at startup
+    programs: Arc<ProgramsResponse>,
+    vkeys_json: Arc<Vec<u8>>,
 }
 
-static STATE: Lazy<AppState> = Lazy::new(|| {
-    utils::setup_logger();
+/// Proofs generated at the same time; each one can use every core, so one by default
+fn prover_workers() -> usize {
+    std::env::var("PROVER_WORKERS")
+        .ok()
+        .and_then(|workers| workers.parse().ok())
+        .filter(|&workers| workers > 0)
+        .unwrap_or(1)
+}
+
+/// ────────────────  Proof systems  ────────────────
+/// Which SP1 proof `POST /prove` or `POST /prove/aggregate` generates
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ProofMode {
+    Core,       // STARK per shard; fastest, for local testing
+    Compressed, // one STARK; can be aggregated
+    Plonk,      // verifiable on-chain
+    Groth16,    // verifiable on-chain, cheapest to verify
+}
+
+impl std::str::FromStr for ProofMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim() {
+            "core" => Ok(Self::Core),
+            "compressed" => Ok(Self::Compressed),
+            "plonk" => Ok(Self::Plonk),
+            "groth16" => Ok(Self::Groth16),
+            other => Err(anyhow::anyhow!("unknown proof mode {other:?}")),
+        }
+    }
+}
+
+impl ProofMode {
+    /// Only PLONK and Groth16 proofs have bytes a Solidity verifier takes
+    fn is_evm(self) -> bool {
+        matches!(self, Self::Plonk | Self::Groth16)
+    }
+}
+
+/// Proof modes this server generates
+struct ProofModes {
+    default: ProofMode,
+    allowed: Vec<ProofMode>,
+}
+
+impl ProofModes {
+    /// `PROOF_MODE` picks the default, Groth16 if unset; `PROOF_MODES` lists the
+    /// allowed modes, comma separated, every mode if unset
+    fn from_env() -> anyhow::Result<Self> {
+        let default = match std::env::var("PROOF_MODE") {
+            Ok(mode) => mode.parse()?,
+            Err(_) => ProofMode::Groth16,
+        };
+        let allowed = match std::env::var("PROOF_MODES") {
+            Ok(modes) => modes
+                .split(',')
+                .map(str::parse)
+                .collect::<anyhow::Result<_>>()?,
+            Err(_) => vec![
+                ProofMode::Core,
+                ProofMode::Compressed,
+                ProofMode::Plonk,
+                ProofMode::Groth16,
+            ],
+        };
+        if !allowed.contains(&default) {
+            anyhow::bail!("default proof mode {default:?} is not in PROOF_MODES");
+        }
+        Ok(Self { default, allowed })
+    }
+
+    /// The mode a request asked for, or the default
+    fn select(&self, requested: Option<ProofMode>) -> Result<ProofMode, (StatusCode, String)> {
+        let mode = requested.unwrap_or(self.default);
+        if !self.allowed.contains(&mode) {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("proof mode {mode:?} is not enabled on this server"),
+            ));
+        }
+        Ok(mode)
+    }
+}
+
+/// Proves `stdin` against `pk` as a `mode` proof
+fn prove_as(
+    state: &AppState,
+    pk: &SP1ProvingKey,
+    stdin: &SP1Stdin,
+    mode: ProofMode,
+) -> Result<SP1ProofWithPublicValues, String> {
+    let prove = state.client.prove(pk, stdin);
+    match mode {
+        ProofMode::Core => prove.core(),
+        ProofMode::Compressed => prove.compressed(),
+        ProofMode::Plonk => prove.plonk(),
+        ProofMode::Groth16 => prove.groth16(),
+    }
+    .run()
+    .map_err(|e| e.to_string())
+}
+
+/// Token the deposit indexer sends to `POST /commitments` and `POST /nullifiers`
+///
+/// Both trees must only follow the chain: a commitment nobody deposited would
+/// prove a note with any balance, and a nullifier nobody spent would lock a
+/// note. So only the indexer watching deposits and settlements writes them,
+/// and without `INDEXER_TOKEN` nothing does.
+fn indexer_token() -> Option<Arc<str>> {
+    std::env::var("INDEXER_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+        .map(Arc::from)
+}
+
+/// Directory of the job and tree store
+fn data_dir() -> String {
+    std::env::var("PROVER_DATA_DIR").unwrap_or_else(|_| "prover-data".to_string())
+}
+
+/// 32-byte hex key sealing unfinished jobs' witnesses at rest, so they can
+/// resume after a restart; the server does not start without it
+fn job_store_key() -> anyhow::Result<[u8; 32]> {
+    let key =
+        std::env::var("JOB_STORE_KEY").map_err(|_| anyhow::anyhow!("JOB_STORE_KEY is not set"))?;
+    hex_to_array(&key).map_err(|e| anyhow::anyhow!("JOB_STORE_KEY must be 32 bytes of hex: {e}"))
+}
+
+/// Set once by `main` before the server takes requests or runs a job
+static STATE: OnceLock<AppState> = OnceLock::new();
+
+fn state() -> &'static AppState {
+    STATE.get().expect("state is set at startup")
+}
+
+fn load_state() -> anyhow::Result<AppState> {
+    // Checked before the slow program setup, so a missing key fails fast
+    let job_store_key = job_store_key()?;
+    let proof_modes = ProofModes::from_env()
+        .map_err(|e| anyhow::anyhow!("invalid proof mode configuration: {e}"))?;
     let client = Arc::new(ProverClient::from_env());
     let (pk, vk) = client.setup(ELF);
-    AppState {
+    let (aggregation_pk, aggregation_vk) = client.setup(AGGREGATION_ELF);
+    let programs = ProgramsResponse {
+        version: RESPONSE_VERSION,
+        programs: vec![
+            ProgramInfo {
+                program_id: ORDER_PROGRAM_ID,
+                vkey_hash: vk.bytes32(),
+            },
+            ProgramInfo {
+                program_id: AGGREGATION_PROGRAM_ID,
+                vkey_hash: aggregation_vk.bytes32(),
+            },
+        ],
+    };
+    let vkeys_json = serde_json::to_vec(&HashMap::from([
+        (ORDER_PROGRAM_ID, &vk),
+        (AGGREGATION_PROGRAM_ID, &aggregation_vk),
+    ]))?;
+    let db = sled::open(data_dir())?;
+    let tree_log = TreeLog::open(&db)?;
+    let (tree, nullifiers) = tree_log.replay()?;
+    Ok(AppState {
         client,
         pk: Arc::new(pk),
         vk: Arc::new(vk),
-    }
-});
+        aggregation_pk: Arc::new(aggregation_pk),
+        aggregation_vk: Arc::new(aggregation_vk),
+        tree: Arc::new(RwLock::new(tree)),
+        nullifiers: Arc::new(RwLock::new(nullifiers)),
+        tree_log: Arc::new(tree_log),
+        jobs: Arc::new(JobQueue::new(
+            JobStore::open(&db, &job_store_key)?,
+            prover_workers(),
+        )),
+        proof_modes: Arc::new(proof_modes),
+        indexer_token: indexer_token(),
+        programs: Arc::new(programs),
+        vkeys_json: Arc::new(vkeys_json),
+    })
+}
 
 /// ────────────────  Helper: decode 0x… hex into fixed array  ────────────────
 fn hex_to_array<const N: usize>(s: &str) -> anyhow::Result<[u8; N]> {
@@ -61,14 +284,38 @@ struct ProveRequest {
     // Private
     order: OrderJson,
     commitment_nullifier: String, // 32-byte hex
-    balance: u64,
-    siblings: Vec<String>, // Vec<32-byte hex>
-    indices: Vec<u8>,
+    balance_asset: String,        // 20-byte hex token the balance is held in
+    balance: U256,
+    siblings: Vec<String>, // Vec<32-byte hex>, exactly TREE_DEPTH of them
+    leaf_index: u64,
+    // Part of the order to fill now; the whole order if omitted
+    #[serde(default)]
+    fill_amount: Option<U256>,
+    // 32-byte hex secret of the change note; derived from the spent note if omitted
+    #[serde(default)]
+    change_nullifier: Option<String>,
+    // Order the change note carries; what is left of `order` if omitted. Needed
+    // when an IOC or FOK order, or a fully filled one, leaves balance over
+    #[serde(default)]
+    change_order: Option<OrderJson>,
+    // "core", "compressed", "plonk" or "groth16"; the server's default if omitted.
+    // Not allowed on the orders of an aggregate, which are always compressed
+    #[serde(default)]
+    mode: Option<ProofMode>,
+}
+
+/// Orders to prove one by one and aggregate into a single proof
+#[derive(Deserialize)]
+struct AggregateRequest {
+    orders: Vec<ProveRequest>,
+    // Mode of the aggregate proof, as for `ProveRequest`
+    #[serde(default)]
+    mode: Option<ProofMode>,
 }
 
 #[derive(Deserialize)]
 struct MarketJson {
-    current_price: u64,
+    current_price: U256,
     block_timestamp: u64,
 }
 
@@ -77,126 +324,836 @@ struct OrderJson {
     wallet_address: String, // 20-byte hex
     token_in: String,       // 20-byte hex
     token_out: String,      // 20-byte hex
-    amount_in: u64,
-    min_amount_out: u64,
-    target_price: u64,
+    amount_in: U256,
+    min_amount_out: U256,
+    target_price: U256,
     deadline: u64,
+    // "sell" or "buy"; a sell order if omitted
+    #[serde(default)]
+    side: OrderSide,
+    // Decimals of the base token, which scale the prices; 18 if omitted
+    #[serde(default = "default_base_decimals")]
+    base_decimals: u8,
+    // "limit", {"stop_loss": {"trigger_price": ...}} or
+    // {"take_profit": {"trigger_price": ...}}; a limit order if omitted
+    #[serde(default)]
+    kind: OrderKind,
+    // "gtc", "ioc" or "fok"; good 'til cancelled if omitted
+    #[serde(default)]
+    time_in_force: TimeInForce,
+    // Earliest block timestamp the order can execute at; no start time if omitted
+    #[serde(default)]
+    valid_after: u64,
+}
+
+fn default_base_decimals() -> u8 {
+    18
+}
+
+impl OrderJson {
+    fn to_order(&self) -> anyhow::Result<OrderData> {
+        Ok(OrderData {
+            wallet_address: hex_to_array::<20>(&self.wallet_address)?,
+            token_in: hex_to_array::<20>(&self.token_in)?,
+            token_out: hex_to_array::<20>(&self.token_out)?,
+            amount_in: self.amount_in,
+            min_amount_out: self.min_amount_out,
+            target_price: self.target_price,
+            deadline: self.deadline,
+            side: self.side,
+            base_decimals: self.base_decimals,
+            kind: self.kind,
+            time_in_force: self.time_in_force,
+            valid_after: self.valid_after,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct CommitmentRequest {
+    commitment_hash: String, // 32-byte hex
+}
+
+#[derive(Deserialize)]
+struct NullifierRequest {
+    nullifier_hash: String, // 32-byte hex
 }
 
 /// ────────────────  Outgoing response  ────────────────
 #[derive(Serialize)]
 struct ProveResponse {
+    version: u32,
+    program_id: &'static str,
+    vkey_hash: String, // `vk.bytes32()`, the on-chain program verification key
+    mode: ProofMode,
     cycles: u64,
     // echoed guest outputs
     valid: bool,
     nullifier_hash: String,
     wallet_address: String,
-    amount_in: u64,
-    min_amount_out: u64,
+    amount_in: U256,
+    min_amount_out: U256,
+    tree_root: String,
+    nullifier_root: String,
+    change_commitment: String,
+    token_in: String,
+    token_out: String,
+    deadline: u64,
+    current_price: U256,
+    block_timestamp: u64,
     // proof
-    proof_b64: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    evm: Option<EvmProof>, // PLONK and Groth16 only
+    proof_b64: String, // for the SP1 SDK
     verified: bool,
+}
 
-    vkey: Arc<SP1VerifyingKey>,
-    pk: Arc<SP1ProvingKey>,
+#[derive(Serialize)]
+struct AggregateResponse {
+    version: u32,
+    program_id: &'static str,
+    vkey_hash: String, // of the aggregation program
+    mode: ProofMode,   // of the aggregate proof
+    cycles: u64,       // summed over the order programs
+    order_vkey_digest: String,
+    nullifier_hashes: Vec<String>,
+    // proof
+    #[serde(skip_serializing_if = "Option::is_none")]
+    evm: Option<EvmProof>, // PLONK and Groth16 only
+    proof_b64: String, // for the SP1 SDK
+    verified: bool,
+}
+
+/// A proof as Solidity takes it; with `vkey_hash` this is everything
+/// `ISP1Verifier.verifyProof` needs
+#[derive(Serialize)]
+struct EvmProof {
+    public_values: String, // ABI-encoded
+    proof_bytes: String,   // `proof.bytes()`
+    calldata: String,      // the whole `OrderServiceManager` call, ready to send
+}
+
+impl EvmProof {
+    fn new<C: SolCall>(
+        proof: &SP1ProofWithPublicValues,
+        call: impl FnOnce(Vec<u8>, Vec<u8>) -> C,
+    ) -> Self {
+        let public_values = proof.public_values.to_vec();
+        let proof_bytes = proof.bytes();
+        let calldata = call(public_values.clone(), proof_bytes.clone()).abi_encode();
+        Self {
+            public_values: to_hex(public_values),
+            proof_bytes: to_hex(proof_bytes),
+            calldata: to_hex(calldata),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ProgramInfo {
+    program_id: &'static str,
+    vkey_hash: String,
+}
+
+#[derive(Serialize)]
+struct ProgramsResponse {
+    version: u32,
+    programs: Vec<ProgramInfo>,
+}
+
+#[derive(Serialize)]
+struct JobResponse {
+    job_id: JobId,
+    #[serde(flatten)]
+    status: JobStatus,
+}
+
+#[derive(Serialize)]
+struct JobRecordResponse {
+    job_id: JobId,
+    #[serde(flatten)]
+    record: JobRecord,
+}
+
+#[derive(Serialize)]
+struct JobSummary {
+    job_id: JobId,
+    state: &'static str,
+}
+
+#[derive(Serialize)]
+struct CommitmentResponse {
+    leaf_index: u64,
+    tree_root: String,
+}
+
+#[derive(Serialize)]
+struct MerklePathResponse {
+    leaf_index: u64,
+    tree_root: String,
+    siblings: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct SpentNullifierResponse {
+    leaf_index: u64,
+    nullifier_root: String,
+}
+
+#[derive(Serialize)]
+struct NonMembershipResponse {
+    nullifier_root: String,
+    low_leaf_value: String,
+    low_leaf_next_value: String,
+    low_leaf_next_index: u64,
+    low_leaf_index: u64,
+    siblings: Vec<String>,
+}
+
+fn to_hex(bytes: impl AsRef<[u8]>) -> String {
+    format!("0x{}", hex::encode(bytes))
 }
 
 fn to_500<E: std::fmt::Display>(err: E) -> (StatusCode, String) {
     (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
 }
 
+/// Malformed client input, such as bad hex
+fn to_400<E: std::fmt::Display>(err: E) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, err.to_string())
+}
+
+/// ────────────────  Order program input  ────────────────
+#[derive(Serialize)]
+struct PublicInputs {
+    market: MarketConditions,
+    tree_root: String,
+    nullifier_hash: String,
+    nullifier_root: String,
+}
+
+/// Everything the order program reads for one request, checked against the
+/// server's trees
+#[derive(Serialize, Deserialize)]
+struct OrderInputs {
+    market: MarketConditions,
+    tree_root: [u8; 32],
+    nullifier_root: [u8; 32],
+    witness: OrderWitness,
+}
+
+impl OrderInputs {
+    fn from_request(state: &AppState, req: &ProveRequest) -> Result<Self, (StatusCode, String)> {
+        // ─── Convert JSON → Rust structs expected by guest ───
+        let market = MarketConditions {
+            current_price: req.market.current_price,
+            block_timestamp: req.market.block_timestamp,
+        };
+
+        let tree_root = hex_to_array::<32>(&req.tree_root).map_err(to_400)?;
+        let known_root = state.tree.read().map_err(to_500)?.is_known_root(&tree_root);
+        if !known_root {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "unknown or expired tree root".to_string(),
+            ));
+        }
+
+        let nullifier_hash_arr = hex_to_array::<32>(&req.nullifier_hash).map_err(to_400)?;
+
+        // ─── Non-membership witness against the current spent-nullifier root ───
+        let (nullifier_root, non_membership) = {
+            let nullifiers = state.nullifiers.read().map_err(to_500)?;
+            let witness = nullifiers
+                .non_membership_proof(&nullifier_hash_arr)
+                .map_err(|e| (StatusCode::CONFLICT, e.to_string()))?;
+            (nullifiers.root(), witness)
+        };
+
+        let order = req.order.to_order().map_err(to_400)?;
+        let change_order = req
+            .change_order
+            .as_ref()
+            .map(OrderJson::to_order)
+            .transpose()
+            .map_err(to_400)?;
+
+        let commitment_nullifier = hex_to_array::<32>(&req.commitment_nullifier).map_err(to_400)?;
+        let balance_asset = hex_to_array::<20>(&req.balance_asset).map_err(to_400)?;
+
+        let change_nullifier = match &req.change_nullifier {
+            Some(change_nullifier) => hex_to_array::<32>(change_nullifier).map_err(to_400)?,
+            None => derive_change_nullifier(
+                &commitment_nullifier,
+                &compute_commitment_hash(
+                    &order,
+                    &commitment_nullifier,
+                    &balance_asset,
+                    req.balance,
+                ),
+            ),
+        };
+
+        let siblings: Vec<[u8; 32]> = req
+            .siblings
+            .iter()
+            .map(|h| hex_to_array::<32>(h))
+            .collect::<Result<_, _>>()
+            .map_err(to_400)?;
+
+        Ok(Self {
+            market,
+            tree_root,
+            nullifier_root,
+            witness: OrderWitness {
+                nullifier_hash: nullifier_hash_arr,
+                order,
+                nullifier: commitment_nullifier,
+                balance_asset,
+                balance: req.balance,
+                siblings,
+                leaf_index: req.leaf_index,
+                non_membership,
+                fill_amount: req.fill_amount.unwrap_or(req.order.amount_in),
+                change_nullifier,
+                change_order,
+            },
+        })
+    }
+
+    /// The inputs the proof makes public; the witness stays private
+    fn public(&self) -> PublicInputs {
+        PublicInputs {
+            market: self.market.clone(),
+            tree_root: to_hex(self.tree_root),
+            nullifier_hash: to_hex(self.witness.nullifier_hash),
+            nullifier_root: to_hex(self.nullifier_root),
+        }
+    }
+
+    /// Builds stdin exactly like in the script
+    fn stdin(&self, mode: ValidationMode) -> SP1Stdin {
+        let mut stdin = SP1Stdin::new();
+        stdin.write(&mode);
+        // public
+        stdin.write(&self.market);
+        stdin.write(&self.tree_root);
+        stdin.write(&self.witness.nullifier_hash);
+        stdin.write(&self.nullifier_root);
+        // private
+        stdin.write(&self.witness.order);
+        stdin.write(&self.witness.nullifier);
+        stdin.write(&self.witness.balance_asset);
+        stdin.write(&self.witness.balance);
+        stdin.write(&self.witness.siblings);
+        stdin.write(&self.witness.leaf_index);
+        stdin.write(&self.witness.non_membership);
+        stdin.write(&self.witness.fill_amount);
+        stdin.write(&self.witness.change_nullifier);
+        stdin.write(&self.witness.change_order);
+        stdin
+    }
+
+    /// Executes in check mode: cycle count + rejection reason, no proof
+    fn check(&self, state: &AppState) -> Result<u64, (StatusCode, String)> {
+        let (check_output, exec_report) = state
+            .client
+            .execute(ELF, &self.stdin(ValidationMode::Check))
+            .run()
+            .map_err(to_500)?;
+
+        let check = OrderCheckResult::abi_decode(check_output.as_slice()).map_err(to_500)?;
+        if check.reason != 0 {
+            let reason = OrderRejection::from_code(check.reason).map_or_else(
+                || format!("unknown reason code {}", check.reason),
+                |r| r.to_string(),
+            );
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("order rejected: {reason}"),
+            ));
+        }
+
+        Ok(exec_report.total_instruction_count())
+    }
+}
+
+/// ────────────────  Proving job  ────────────────
+/// A queued proof request; its inputs stay on disk, sealed, until it finishes
+/// so it can run again after a restart (see `store`)
+#[derive(Serialize, Deserialize)]
+enum ProofJob {
+    Order {
+        inputs: Box<OrderInputs>,
+        mode: ProofMode,
+    },
+    Aggregate {
+        orders: Vec<OrderInputs>,
+        mode: ProofMode,
+    },
+}
+
+impl Job for ProofJob {
+    fn redacted(&self) -> serde_json::Value {
+        let public = match self {
+            Self::Order { inputs, .. } => serde_json::to_value(inputs.public()),
+            Self::Aggregate { orders, .. } => {
+                serde_json::to_value(orders.iter().map(OrderInputs::public).collect::<Vec<_>>())
+            }
+        };
+        public.unwrap_or_default()
+    }
+
+    fn run(&self, job: &JobHandle) -> Result<JobOutput, String> {
+        match self {
+            Self::Order { inputs, mode } => prove_order(state(), inputs, *mode, job),
+            Self::Aggregate { orders, mode } => prove_aggregate(state(), orders, *mode, job),
+        }
+    }
+}
+
 /// ────────────────  Route handler  ────────────────
+/// Checks the request against the trees now and queues the proof
 async fn prove_handler(
     State(state): State<AppState>,
     Json(req): Json<ProveRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    // ─── Convert JSON → Rust structs expected by guest ───
-    let market = MarketConditions {
-        current_price: req.market.current_price,
-        block_timestamp: req.market.block_timestamp,
-    };
+    let mode = state.proof_modes.select(req.mode)?;
+    let inputs = OrderInputs::from_request(&state, &req)?;
+    let job_id = state
+        .jobs
+        .submit(ProofJob::Order {
+            inputs: Box::new(inputs),
+            mode,
+        })
+        .map_err(to_500)?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(JobResponse {
+            job_id,
+            status: JobStatus::Queued,
+        }),
+    ))
+}
+
+/// Job body for `POST /prove`; runs on a prover worker
+fn prove_order(
+    state: &AppState,
+    inputs: &OrderInputs,
+    mode: ProofMode,
+    job: &JobHandle,
+) -> Result<JobOutput, String> {
+    let cycles = inputs.check(state).map_err(|(_, e)| e)?;
+
+    // ─── Prove & verify in enforcing mode ───
+    job.advance(JobStatus::Proving)?;
+    let proof = prove_as(
+        state,
+        &state.pk,
+        &inputs.stdin(ValidationMode::Enforce),
+        mode,
+    )?;
 
-    let tree_root = hex_to_array::<32>(&req.tree_root).map_err(to_500)?;
-    let nullifier_hash_arr = hex_to_array::<32>(&req.nullifier_hash).map_err(to_500)?;
-
-    let order = OrderData {
-        wallet_address: hex_to_array::<20>(&req.order.wallet_address).map_err(to_500)?,
-        token_in: hex_to_array::<20>(&req.order.token_in).map_err(to_500)?,
-        token_out: hex_to_array::<20>(&req.order.token_out).map_err(to_500)?,
-        amount_in: req.order.amount_in,
-        min_amount_out: req.order.min_amount_out,
-        target_price: req.order.target_price,
-        deadline: req.order.deadline,
+    let verified = state.client.verify(&proof, &state.vk).is_ok();
+
+    // ─── Decode guest-committed outputs ───
+    let public_values =
+        OrderPublicValues::abi_decode(proof.public_values.as_slice()).map_err(|e| e.to_string())?;
+
+    // ─── Serialize proof to b64 ───
+    let proof_bytes = serde_json::to_vec(&proof).map_err(|e| e.to_string())?; // Vec<u8>
+    let proof_b64 = general_purpose::URL_SAFE_NO_PAD.encode(&proof_bytes);
+
+    let response = ProveResponse {
+        version: RESPONSE_VERSION,
+        program_id: ORDER_PROGRAM_ID,
+        vkey_hash: state.vk.bytes32(),
+        mode,
+        cycles,
+        valid: public_values.valid,
+        nullifier_hash: to_hex(public_values.nullifierHash),
+        wallet_address: to_hex(public_values.walletAddress),
+        amount_in: public_values.amountIn,
+        min_amount_out: public_values.minAmountOut,
+        tree_root: to_hex(public_values.treeRoot),
+        nullifier_root: to_hex(public_values.nullifierRoot),
+        change_commitment: to_hex(public_values.changeCommitment),
+        token_in: to_hex(public_values.tokenIn),
+        token_out: to_hex(public_values.tokenOut),
+        deadline: public_values.deadline,
+        current_price: public_values.currentPrice,
+        block_timestamp: public_values.blockTimestamp,
+        evm: mode.is_evm().then(|| {
+            EvmProof::new(&proof, |public_values, proof_bytes| verifyOrderProofCall {
+                publicValues: public_values.into(),
+                proofBytes: proof_bytes.into(),
+            })
+        }),
+        proof_b64,
+        verified,
     };
 
-    let commitment_nullifier = hex_to_array::<32>(&req.commitment_nullifier).map_err(to_500)?;
+    Ok(JobOutput {
+        result: serde_json::to_value(response).map_err(|e| e.to_string())?,
+        public_values: proof.public_values.to_vec(),
+        proof: proof_bytes,
+    })
+}
 
-    let siblings: Vec<[u8; 32]> = req
-        .siblings
+/// ────────────────  Aggregated proof  ────────────────
+async fn aggregate_handler(
+    State(state): State<AppState>,
+    Json(req): Json<AggregateRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let mode = state.proof_modes.select(req.mode)?;
+    if req.orders.iter().any(|order| order.mode.is_some()) {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "orders of an aggregate are always compressed; set `mode` on the aggregate".to_string(),
+        ));
+    }
+    let inputs = req
+        .orders
         .iter()
-        .map(|h| hex_to_array::<32>(h))
-        .collect::<Result<_, _>>()
+        .map(|order| OrderInputs::from_request(&state, order))
+        .collect::<Result<Vec<_>, _>>()?;
+    let job_id = state
+        .jobs
+        .submit(ProofJob::Aggregate {
+            orders: inputs,
+            mode,
+        })
         .map_err(to_500)?;
 
-    // ─── Build stdin exactly like in your script ───
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(JobResponse {
+            job_id,
+            status: JobStatus::Queued,
+        }),
+    ))
+}
+
+/// Job body for `POST /prove/aggregate`; runs on a prover worker
+fn prove_aggregate(
+    state: &AppState,
+    inputs: &[OrderInputs],
+    mode: ProofMode,
+    job: &JobHandle,
+) -> Result<JobOutput, String> {
+    let mut cycles = 0;
+    for order in inputs {
+        cycles += order.check(state).map_err(|(_, e)| e)?;
+    }
+
+    // ─── Compressed proof per order; these can be verified inside SP1 ───
+    job.advance(JobStatus::Proving)?;
+    let mut order_proofs = Vec::with_capacity(inputs.len());
+    for order in inputs {
+        let proof = state
+            .client
+            .prove(&state.pk, &order.stdin(ValidationMode::Enforce))
+            .compressed()
+            .run()
+            .map_err(|e| e.to_string())?;
+        order_proofs.push(proof);
+        job.advance(JobStatus::Proving)?; // Stop between order proofs once cancelled
+    }
+
+    // ─── Same checks the aggregation program makes, before paying for the proof ───
+    let public_values: Vec<Vec<u8>> = order_proofs
+        .iter()
+        .map(|proof| proof.public_values.to_vec())
+        .collect();
+    let orders = try_aggregate_orders(&public_values)
+        .map_err(|rejection| format!("aggregate rejected: {rejection}"))?;
+
+    // ─── Wrap every order proof into one proof ───
     let mut stdin = SP1Stdin::new();
-    // public
-    stdin.write(&market);
-    stdin.write(&tree_root);
-    stdin.write(&nullifier_hash_arr);
-    // private
-    stdin.write(&order);
-    stdin.write(&commitment_nullifier);
-    stdin.write(&req.balance);
-    stdin.write(&siblings);
-    stdin.write(&req.indices);
-
-    // ─── Execute for cycle count (optional) ───
-    let (_, exec_report) = state.client.execute(ELF, &stdin).run().map_err(to_500)?;
-    let cycles = exec_report.total_instruction_count();
-
-    // ─── Prove & verify (unchanged) ───
-    let mut proof = state
-        .client
-        .prove(&state.pk, &stdin)
-        .groth16()
-        .run()
-        .map_err(to_500)?;
+    stdin.write(&state.vk.hash_u32());
+    stdin.write(&public_values);
+    for proof in order_proofs {
+        let SP1Proof::Compressed(proof) = proof.proof else {
+            return Err("order proof is not compressed".to_string());
+        };
+        stdin.write_proof(*proof, state.vk.vk.clone());
+    }
 
-    let verified = state.client.verify(&proof, &state.vk).is_ok();
+    let proof = prove_as(state, &state.aggregation_pk, &stdin, mode)?;
 
-    // ─── Read guest-committed outputs ───
-    let valid = proof.public_values.read::<bool>();
-    let out_nullifier = proof.public_values.read::<[u8; 32]>();
-    let out_wallet = proof.public_values.read::<[u8; 20]>();
-    let amount_in = proof.public_values.read::<u64>();
-    let min_amount_out = proof.public_values.read::<u64>();
+    let verified = state.client.verify(&proof, &state.aggregation_vk).is_ok();
 
-    // ─── Serialize proof to b64 ───
-    let proof_bytes = serde_json::to_vec(&proof).map_err(to_500)?; // Vec<u8>
-    let proof_b64 = general_purpose::URL_SAFE_NO_PAD.encode(&proof_bytes);
+    let aggregate = AggregatePublicValues::abi_decode(proof.public_values.as_slice())
+        .map_err(|e| e.to_string())?;
+    if aggregate.orderVKeyDigest != vkey_digest(&state.vk.hash_u32()) {
+        return Err("aggregate proof is for another order program".to_string());
+    }
+
+    let proof_bytes = serde_json::to_vec(&proof).map_err(|e| e.to_string())?;
 
-    // ─── Return JSON ───
-    Ok(Json(ProveResponse {
+    let response = AggregateResponse {
+        version: RESPONSE_VERSION,
+        program_id: AGGREGATION_PROGRAM_ID,
+        vkey_hash: state.aggregation_vk.bytes32(),
+        mode,
         cycles,
-        valid,
-        nullifier_hash: format!("0x{}", hex::encode(out_nullifier)),
-        wallet_address: format!("0x{}", hex::encode(out_wallet)),
-        amount_in,
-        min_amount_out,
-        proof_b64,
+        order_vkey_digest: to_hex(aggregate.orderVKeyDigest),
+        nullifier_hashes: orders.iter().map(|o| to_hex(o.nullifierHash)).collect(),
+        evm: mode.is_evm().then(|| {
+            EvmProof::new(&proof, |public_values, proof_bytes| {
+                verifyAggregatedProofCall {
+                    publicValues: public_values.into(),
+                    proofBytes: proof_bytes.into(),
+                }
+            })
+        }),
+        proof_b64: general_purpose::URL_SAFE_NO_PAD.encode(&proof_bytes),
         verified,
-        vkey: state.vk,
-        pk: state.pk,
+    };
+
+    Ok(JobOutput {
+        result: serde_json::to_value(response).map_err(|e| e.to_string())?,
+        public_values: proof.public_values.to_vec(),
+        proof: proof_bytes,
+    })
+}
+
+/// ────────────────  Programs  ────────────────
+/// Program IDs and verification key hashes
+async fn program_handler(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.programs.clone())
+}
+
+/// Full verifying keys by program ID; they only change with the ELFs, so
+/// clients fetch them once
+async fn vkey_handler(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "application/json"),
+            (header::CACHE_CONTROL, "public, max-age=86400"),
+        ],
+        state.vkeys_json.to_vec(),
+    )
+}
+
+/// ────────────────  Proving jobs  ────────────────
+async fn list_jobs_handler(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let jobs = state.jobs.store().list().map_err(to_500)?;
+
+    Ok(Json(
+        jobs.into_iter()
+            .map(|(job_id, status)| JobSummary {
+                job_id,
+                state: status.state(),
+            })
+            .collect::<Vec<_>>(),
+    ))
+}
+
+async fn job_handler(
+    State(state): State<AppState>,
+    Path(job_id): Path<JobId>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let record = state
+        .jobs
+        .store()
+        .get(job_id)
+        .map_err(to_500)?
+        .ok_or((StatusCode::NOT_FOUND, format!("no job {job_id}")))?;
+
+    Ok(Json(JobRecordResponse { job_id, record }))
+}
+
+/// The finished proof, as `SP1ProofWithPublicValues` JSON
+async fn job_proof_handler(
+    State(state): State<AppState>,
+    Path(job_id): Path<JobId>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let proof = state
+        .jobs
+        .store()
+        .proof(job_id)
+        .map_err(to_500)?
+        .ok_or((StatusCode::NOT_FOUND, format!("no proof for job {job_id}")))?;
+
+    Ok(([(header::CONTENT_TYPE, "application/json")], proof))
+}
+
+async fn cancel_job_handler(
+    State(state): State<AppState>,
+    Path(job_id): Path<JobId>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let status = state
+        .jobs
+        .cancel(job_id)
+        .map_err(to_500)?
+        .ok_or((StatusCode::NOT_FOUND, format!("no job {job_id}")))?;
+    if !matches!(status, JobStatus::Cancelled) {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("job {job_id} has already finished"),
+        ));
+    }
+
+    Ok(Json(JobResponse { job_id, status }))
+}
+
+/// ────────────────  Commitment tree  ────────────────
+/// Rejects tree writes from anyone but the indexer
+fn authorize_indexer(state: &AppState, headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+    let Some(token) = &state.indexer_token else {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "tree writes are disabled; set INDEXER_TOKEN".to_string(),
+        ));
+    };
+    let sent = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    // Compares every byte, so the time taken says nothing about the token
+    let matches = sent.len() == token.len()
+        && sent
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0;
+    if !matches {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "invalid indexer token".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Appends a deposited note's commitment; indexer only
+async fn append_commitment_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<CommitmentRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    authorize_indexer(&state, &headers)?;
+    let commitment_hash = hex_to_array::<32>(&req.commitment_hash).map_err(to_400)?;
+
+    let mut tree = state.tree.write().map_err(to_500)?;
+    let index = tree.len();
+    state
+        .tree_log
+        .log_commitment(index, commitment_hash)
+        .map_err(to_500)?;
+    let leaf_index = match tree.append(commitment_hash) {
+        Ok(leaf_index) => leaf_index,
+        Err(err) => {
+            state.tree_log.unlog_commitment(index).map_err(to_500)?;
+            return Err((StatusCode::CONFLICT, err.to_string()));
+        }
+    };
+
+    Ok(Json(CommitmentResponse {
+        leaf_index,
+        tree_root: to_hex(tree.root()),
+    }))
+}
+
+async fn merkle_path_handler(
+    State(state): State<AppState>,
+    Path(leaf_index): Path<u64>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let tree = state.tree.read().map_err(to_500)?;
+    let siblings = tree
+        .proof(leaf_index)
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+    Ok(Json(MerklePathResponse {
+        leaf_index,
+        tree_root: to_hex(tree.root()),
+        siblings: siblings.iter().map(to_hex).collect(),
+    }))
+}
+
+/// ────────────────  Spent-nullifier tree  ────────────────
+/// Marks a nullifier spent once its settlement lands; indexer only
+async fn spend_nullifier_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<NullifierRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    authorize_indexer(&state, &headers)?;
+    let nullifier_hash = hex_to_array::<32>(&req.nullifier_hash).map_err(to_400)?;
+
+    let mut nullifiers = state.nullifiers.write().map_err(to_500)?;
+    let index = nullifiers.len();
+    state
+        .tree_log
+        .log_nullifier(index, nullifier_hash)
+        .map_err(to_500)?;
+    let leaf_index = match nullifiers.insert(nullifier_hash) {
+        Ok(leaf_index) => leaf_index,
+        Err(err) => {
+            state.tree_log.unlog_nullifier(index).map_err(to_500)?;
+            return Err((StatusCode::CONFLICT, err.to_string()));
+        }
+    };
+
+    Ok(Json(SpentNullifierResponse {
+        leaf_index,
+        nullifier_root: to_hex(nullifiers.root()),
+    }))
+}
+
+async fn non_membership_handler(
+    State(state): State<AppState>,
+    Path(nullifier_hash): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let nullifier_hash = hex_to_array::<32>(&nullifier_hash).map_err(to_400)?;
+
+    let nullifiers = state.nullifiers.read().map_err(to_500)?;
+    let witness = nullifiers
+        .non_membership_proof(&nullifier_hash)
+        .map_err(|e| (StatusCode::CONFLICT, e.to_string()))?;
+
+    Ok(Json(NonMembershipResponse {
+        nullifier_root: to_hex(nullifiers.root()),
+        low_leaf_value: to_hex(witness.low_leaf.value),
+        low_leaf_next_value: to_hex(witness.low_leaf.next_value),
+        low_leaf_next_index: witness.low_leaf.next_index,
+        low_leaf_index: witness.low_leaf_index,
+        siblings: witness.siblings.iter().map(to_hex).collect(),
     }))
 }
 
 /// ────────────────  Tokio main ────────────────
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    utils::setup_logger();
+    let state = load_state()?;
+    let state = STATE.get_or_init(|| state);
+    let resumed = state.jobs.resume()?;
+    if resumed > 0 {
+        println!("resuming {resumed} unfinished proving jobs");
+    }
+
     let app = Router::new()
         .route("/prove", post(prove_handler))
-        .with_state(STATE.clone());
+        .route("/prove/aggregate", post(aggregate_handler))
+        .route("/program", get(program_handler))
+        .route("/vkey", get(vkey_handler))
+        .route("/jobs", get(list_jobs_handler))
+        .route("/jobs/:job_id", get(job_handler).delete(cancel_job_handler))
+        .route("/jobs/:job_id/proof", get(job_proof_handler))
+        .route("/commitments", post(append_commitment_handler))
+        .route("/commitments/:leaf_index/path", get(merkle_path_handler))
+        .route("/nullifiers", post(spend_nullifier_handler))
+        .route(
+            "/nullifiers/:nullifier_hash/witness",
+            get(non_membership_handler),
+        )
+        .with_state(state.clone());
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await?;
     println!("dark-pool server listening on {}", listener.local_addr()?);
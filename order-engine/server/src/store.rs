@@ -0,0 +1,297 @@
+//! On-disk job and tree store
+//!
+//! Everything is kept in one sled database under `PROVER_DATA_DIR`.
+//!
+//! The leaves of the commitment and spent-nullifier trees are logged in the
+//! order they were added, and both trees are rebuilt from the log at startup,
+//! so every root the server knew is still accepted after a restart.
+//!
+//! A job's record
+//! holds its status and public inputs. A finished proof is kept next to its
+//! record.
+//!
+//! Running a job again after a restart takes its full inputs, private witness
+//! included. sled is log-structured, so dropping them once the job finishes
+//! does not erase their bytes. They are therefore sealed with ChaCha20-Poly1305
+//! under a server key before they reach the disk. The server does not start
+//! without the key, so every unfinished job resumes; only one whose inputs do
+//! not open under the current key, say after the key was rotated, fails and
+//! has to be submitted again.
+
+use crate::jobs::{JobId, JobOutput, JobStatus};
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, Nonce,
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+};
+use fibonacci_lib::{IncrementalMerkleTree, NullifierTree};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+/// What the store keeps of a job
+#[derive(Clone, Serialize, Deserialize)]
+pub struct JobRecord {
+    #[serde(flatten)]
+    pub status: JobStatus,
+    /// Public inputs only
+    pub inputs: serde_json::Value,
+    /// Hex ABI-encoded public values, once done
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_values: Option<String>,
+}
+
+pub struct JobStore {
+    db: sled::Db,
+    records: sled::Tree,
+    pending: sled::Tree, // Sealed full inputs of unfinished jobs
+    proofs: sled::Tree,
+    // Serializes new IDs and read-modify-write of records
+    lock: Mutex<()>,
+    // Seals `pending`
+    cipher: ChaCha20Poly1305,
+}
+
+/// Why an unfinished job whose inputs do not open fails after a restart
+const NOT_RESUMABLE: &str = "the server restarted before the job finished; submit it again";
+
+fn key(id: JobId) -> [u8; 8] {
+    id.to_be_bytes()
+}
+
+fn id(key: &[u8]) -> anyhow::Result<JobId> {
+    Ok(JobId::from_be_bytes(key.try_into()?))
+}
+
+impl JobStore {
+    /// Opens the store; `payload_key` seals what unfinished jobs need to resume
+    pub fn open(db: &sled::Db, payload_key: &[u8; 32]) -> anyhow::Result<Self> {
+        Ok(Self {
+            records: db.open_tree("records")?,
+            pending: db.open_tree("pending")?,
+            proofs: db.open_tree("proofs")?,
+            db: db.clone(),
+            lock: Mutex::new(()),
+            cipher: ChaCha20Poly1305::new(Key::from_slice(payload_key)),
+        })
+    }
+
+    /// Stores a new queued job and returns its ID
+    ///
+    /// `payload` is everything needed to run the job again, and is only stored
+    /// sealed; `inputs` is what is kept of it once the job finishes.
+    pub fn insert(&self, inputs: serde_json::Value, payload: &[u8]) -> anyhow::Result<JobId> {
+        let _guard = self.lock.lock().unwrap();
+        let last = self.records.last()?.map(|(key, _)| id(&key)).transpose()?;
+        let id = last.map_or(1, |last| last + 1);
+        let record = JobRecord {
+            status: JobStatus::Queued,
+            inputs,
+            public_values: None,
+        };
+        // Payload first: a record without one could never run again
+        self.pending
+            .insert(key(id), seal(&self.cipher, id, payload)?)?;
+        self.records.insert(key(id), serde_json::to_vec(&record)?)?;
+        self.db.flush()?;
+        Ok(id)
+    }
+
+    pub fn get(&self, id: JobId) -> anyhow::Result<Option<JobRecord>> {
+        self.records
+            .get(key(id))?
+            .map(|bytes| Ok(serde_json::from_slice(&bytes)?))
+            .transpose()
+    }
+
+    /// IDs and statuses of every job, oldest first
+    pub fn list(&self) -> anyhow::Result<Vec<(JobId, JobStatus)>> {
+        self.records
+            .iter()
+            .map(|entry| {
+                let (key, bytes) = entry?;
+                let record: JobRecord = serde_json::from_slice(&bytes)?;
+                Ok((id(&key)?, record.status))
+            })
+            .collect()
+    }
+
+    /// The finished proof as JSON
+    pub fn proof(&self, id: JobId) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.proofs.get(key(id))?.map(|bytes| bytes.to_vec()))
+    }
+
+    /// Payloads of the jobs that have not finished, oldest first
+    ///
+    /// An unfinished job whose payload was not stored, or no longer opens under
+    /// the current key, fails instead.
+    pub fn unfinished(&self) -> anyhow::Result<Vec<(JobId, Vec<u8>)>> {
+        // Left behind by a stop between finishing a job and dropping its payload
+        for entry in self.pending.iter() {
+            let (key, _) = entry?;
+            if !self
+                .get(id(&key)?)?
+                .is_some_and(|record| !record.status.is_final())
+            {
+                self.pending.remove(key)?;
+            }
+        }
+
+        let mut jobs = Vec::new();
+        for entry in self.records.iter() {
+            let (key, bytes) = entry?;
+            let record: JobRecord = serde_json::from_slice(&bytes)?;
+            if record.status.is_final() {
+                continue;
+            }
+            let id = id(&key)?;
+            let payload = self
+                .pending
+                .get(&key)?
+                .and_then(|sealed| open(&self.cipher, id, &sealed));
+            match payload {
+                Some(payload) => jobs.push((id, payload)),
+                None => self.finish(id, Err(NOT_RESUMABLE.to_string()))?,
+            }
+        }
+        Ok(jobs)
+    }
+
+    /// Moves an unfinished job to `status` and returns its status after the call
+    pub fn advance(&self, id: JobId, status: JobStatus) -> anyhow::Result<Option<JobStatus>> {
+        let _guard = self.lock.lock().unwrap();
+        let Some(mut record) = self.get(id)? else {
+            return Ok(None);
+        };
+        if !record.status.is_final() {
+            record.status = status;
+            self.records.insert(key(id), serde_json::to_vec(&record)?)?;
+        }
+        Ok(Some(record.status))
+    }
+
+    /// Cancels an unfinished job and returns its status after the call
+    pub fn cancel(&self, id: JobId) -> anyhow::Result<Option<JobStatus>> {
+        let status = self.advance(id, JobStatus::Cancelled)?;
+        if matches!(status, Some(JobStatus::Cancelled)) {
+            self.pending.remove(key(id))?;
+            self.db.flush()?;
+        }
+        Ok(status)
+    }
+
+    /// Records a job's outcome unless it was cancelled while running, and drops
+    /// its private inputs
+    pub fn finish(&self, id: JobId, outcome: Result<JobOutput, String>) -> anyhow::Result<()> {
+        {
+            let _guard = self.lock.lock().unwrap();
+            if let Some(mut record) = self.get(id)?
+                && !record.status.is_final()
+            {
+                match outcome {
+                    Ok(output) => {
+                        self.proofs.insert(key(id), output.proof)?;
+                        record.public_values =
+                            Some(format!("0x{}", hex::encode(output.public_values)));
+                        record.status = JobStatus::Done {
+                            result: output.result,
+                        };
+                    }
+                    Err(error) => record.status = JobStatus::Failed { error },
+                }
+                self.records.insert(key(id), serde_json::to_vec(&record)?)?;
+            }
+        }
+        self.pending.remove(key(id))?;
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+/// Leaves of the server's commitment and spent-nullifier trees, oldest first
+pub struct TreeLog {
+    db: sled::Db,
+    commitments: sled::Tree,
+    nullifiers: sled::Tree,
+}
+
+impl TreeLog {
+    pub fn open(db: &sled::Db) -> anyhow::Result<Self> {
+        Ok(Self {
+            commitments: db.open_tree("commitments")?,
+            nullifiers: db.open_tree("nullifiers")?,
+            db: db.clone(),
+        })
+    }
+
+    /// Both trees as they were when the last leaf was logged
+    pub fn replay(&self) -> anyhow::Result<(IncrementalMerkleTree, NullifierTree)> {
+        let mut tree = IncrementalMerkleTree::default();
+        for entry in self.commitments.iter() {
+            tree.append(leaf(&entry?.1)?)?;
+        }
+        let mut nullifiers = NullifierTree::new();
+        for entry in self.nullifiers.iter() {
+            nullifiers.insert(leaf(&entry?.1)?)?;
+        }
+        Ok((tree, nullifiers))
+    }
+
+    /// Logs the `index`-th commitment, before it goes into the tree
+    pub fn log_commitment(&self, index: u64, commitment_hash: [u8; 32]) -> anyhow::Result<()> {
+        self.commitments.insert(key(index), &commitment_hash)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Logs the `index`-th spent nullifier, before it goes into the tree
+    pub fn log_nullifier(&self, index: u64, nullifier_hash: [u8; 32]) -> anyhow::Result<()> {
+        self.nullifiers.insert(key(index), &nullifier_hash)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Drops a logged leaf the tree then refused
+    pub fn unlog_commitment(&self, index: u64) -> anyhow::Result<()> {
+        self.commitments.remove(key(index))?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub fn unlog_nullifier(&self, index: u64) -> anyhow::Result<()> {
+        self.nullifiers.remove(key(index))?;
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+fn leaf(bytes: &[u8]) -> anyhow::Result<[u8; 32]> {
+    Ok(bytes.try_into()?)
+}
+
+/// Encrypts a job's payload as nonce ‖ ciphertext, bound to its ID
+fn seal(cipher: &ChaCha20Poly1305, id: JobId, payload: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: payload,
+                aad: &key(id),
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("cannot seal the payload of job {id}"))?;
+    Ok([nonce.as_slice(), &ciphertext].concat())
+}
+
+/// Decrypts what [`seal`] stored for job `id`, if it opens under this key
+fn open(cipher: &ChaCha20Poly1305, id: JobId, sealed: &[u8]) -> Option<Vec<u8>> {
+    let (nonce, ciphertext) = sealed.split_at_checked(12)?;
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: &key(id),
+            },
+        )
+        .ok()
+}